@@ -20,6 +20,7 @@ syn match bugOperator display "\%(+\|-\|/\|*\|=\|\^\|&\||\|!\|>\|<\|%\)=\?"
 syntax region bugString start=/\v"/ skip=/\v\\./ end=/\v"/
 syn region bugNumber start=/\d/ skip=/\d/ end=/\s/
 syn match bugArrow display "->"
+syn match bugComment "//.*$"
 
 hi def link bugKeyword Keyword
 hi def link bugKeyword Keyword
@@ -30,5 +31,6 @@ hi def link bugOperator Operator
 hi def link bugNumber Number
 hi def link bugString String
 hi def link bugBool Boolean
+hi def link bugComment Comment
 
 let b:current_syntax = "bug"
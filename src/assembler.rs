@@ -0,0 +1,474 @@
+use crate::bytecode::{ByteCodeStream, Opcode};
+use crate::{DefinedFn, Object, Program};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Builds a `Program` from its textual form, the same format `disassembler::disassemble` prints.
+///
+/// ```text
+/// const #0 str "Hello, world!"
+///
+/// fn main arity=0 locals=0
+///     LDC #0
+///     INVOKE_NATIVE write
+///     RETURN
+/// end
+/// ```
+///
+/// Everything after a `;` is a comment, offsets in front of instructions are informative and ignored,
+/// `name:` defines a label for `JUMP`/`JUMPNOTIF` and `LDC` also accepts a literal, which gets added to the pool.
+/// `INVOKE` calls a function of the file, defined before or after the call, and `INVOKE_NATIVE` a native.
+/// Constants are `int`, `str`, `bool` or `list`, written like `[1, "a", [true]]`.
+pub fn assemble(source: &str) -> Result<Program, AssemblerError> {
+    let mut assembler = Assembler { program: Program::new(), function: None, undefined: HashMap::new() };
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = Line::new(index + 1, strip_comment(raw_line));
+        if !line.text.is_empty() {
+            assembler.line(&line)?;
+        }
+    }
+    if let Some(function) = assembler.function {
+        return Err(AssemblerError::new(function.line, format!("Function `{}` is missing its `end`", function.name)));
+    }
+    if let Some((name, line)) = assembler.undefined.into_iter().min_by_key(|(_, line)| *line) {
+        let message = format!("Call to undefined function `{}`, natives are called with `INVOKE_NATIVE`", name);
+        return Err(AssemblerError::new(line, message));
+    }
+    Ok(assembler.program)
+}
+
+#[derive(Debug)]
+pub struct AssemblerError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AssemblerError {
+    fn new(line: usize, message: String) -> Self {
+        Self { line, message }
+    }
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+struct Line<'a> {
+    number: usize,
+    text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn new(number: usize, text: &'a str) -> Self {
+        Self { number, text: text.trim() }
+    }
+
+    fn error(&self, message: String) -> AssemblerError {
+        AssemblerError::new(self.number, message)
+    }
+}
+
+/// A function whose `end` wasn't reached yet
+struct PendingFn {
+    name: String,
+    line: usize,
+    arity: usize,
+    max_locals: usize,
+    code: Vec<Opcode>,
+    labels: HashMap<String, usize>,
+    /// Jumps waiting for their label: offset of the jump, label and line of the jump
+    fixups: Vec<(usize, String, usize)>,
+}
+
+struct Assembler {
+    program: Program,
+    function: Option<PendingFn>,
+    /// Functions called but not defined yet, with the line of their first call
+    undefined: HashMap<String, usize>,
+}
+
+impl Assembler {
+    fn line(&mut self, line: &Line) -> Result<(), AssemblerError> {
+        let (keyword, rest) = split_word(line.text);
+        match (keyword, self.function.is_some()) {
+            ("const", false) => self.constant(line, rest),
+            ("fn", false) => self.function(line, rest),
+            ("end", true) => self.end(line),
+            ("const" | "fn", true) => Err(line.error(format!("`{}` inside of a function, missing `end`?", keyword))),
+            (_, true) => self.instruction_or_label(line),
+            (_, false) => Err(line.error(format!("Expecting `const` or `fn` but got `{}`", keyword))),
+        }
+    }
+
+    fn constant(&mut self, line: &Line, rest: &str) -> Result<(), AssemblerError> {
+        let mut rest = rest;
+        if rest.starts_with('#') {
+            let (index, remaining) = split_word(rest);
+            let expected = self.program.pool.entries.len();
+            if parse_number::<usize>(line, &index[1..])? != expected {
+                return Err(line.error(format!("Constant `{}` is out of order, expecting `#{}`", index, expected)));
+            }
+            rest = remaining;
+        }
+        let (typ, literal) = split_word(rest);
+        let object = match typ {
+            "int" => Object::Integer(parse_number(line, literal)?),
+            "bool" => Object::Boolean(parse_number(line, literal)?),
+            "str" => Object::String(parse_string(line, literal)?),
+            "list" => parse_list(line, literal)?,
+            _ => {
+                let message = format!("Unknown constant type `{}`, expecting `int`, `str`, `bool` or `list`", typ);
+                return Err(line.error(message));
+            }
+        };
+        self.program.pool.append(object);
+        Ok(())
+    }
+
+    fn function(&mut self, line: &Line, rest: &str) -> Result<(), AssemblerError> {
+        let (name, mut rest) = split_word(rest);
+        if name.is_empty() {
+            return Err(line.error("Expecting the function's name after `fn`".to_string()));
+        }
+        if self.program.function_index(name).is_some() && self.undefined.remove(name).is_none() {
+            return Err(line.error(format!("Function `{}` is already defined", name)));
+        }
+        // Numbered right away, so that it can call itself
+        self.program.declare_function(name);
+        let mut arity = 0;
+        let mut max_locals = None;
+        while !rest.is_empty() {
+            let (attribute, remaining) = split_word(rest);
+            match attribute.split_once('=') {
+                Some(("arity", value)) => arity = parse_number(line, value)?,
+                Some(("locals", value)) => max_locals = Some(parse_number(line, value)?),
+                _ => return Err(line.error(format!("Unknown function attribute `{}`", attribute))),
+            }
+            rest = remaining;
+        }
+        self.function = Some(PendingFn {
+            name: name.to_string(),
+            line: line.number,
+            arity,
+            max_locals: max_locals.unwrap_or(arity),
+            code: vec![],
+            labels: HashMap::new(),
+            fixups: vec![],
+        });
+        Ok(())
+    }
+
+    fn end(&mut self, line: &Line) -> Result<(), AssemblerError> {
+        let mut function = self.function.take().unwrap();
+        for (offset, label, jump_line) in &function.fixups {
+            let Some(target) = function.labels.get(label) else {
+                return Err(AssemblerError::new(*jump_line, format!("Undefined label `{}`", label)));
+            };
+            function.code[*offset] = match function.code[*offset] {
+                Opcode::JUMP(_) => Opcode::JUMP(*target),
+                _ => Opcode::JUMPNOTIF(*target),
+            };
+        }
+        if function.max_locals < function.arity {
+            return Err(line.error(format!("Function `{}` has less locals than parameters", function.name)));
+        }
+        let code = ByteCodeStream::from(function.code);
+        let index = self.program.declare_function(&function.name);
+        self.program.fns[index] =
+            DefinedFn::new(0, function.arity, code, function.max_locals).with_name(&function.name);
+        Ok(())
+    }
+
+    fn instruction_or_label(&mut self, line: &Line) -> Result<(), AssemblerError> {
+        let function = self.function.as_mut().unwrap();
+        if let Some(label) = line.text.strip_suffix(':') {
+            if !is_identifier(label) {
+                return Err(line.error(format!("Invalid label name `{}`", label)));
+            }
+            if function.labels.insert(label.to_string(), function.code.len()).is_some() {
+                return Err(line.error(format!("Label `{}` is already defined", label)));
+            }
+            return Ok(());
+        }
+
+        let (mut mnemonic, mut operand) = split_word(line.text);
+        if mnemonic.chars().all(|chr| chr.is_ascii_digit()) {
+            (mnemonic, operand) = split_word(operand);
+        }
+        let offset = function.code.len();
+        let opcode = match mnemonic {
+            "NOP" => Opcode::NOP,
+            "IADD" => Opcode::IADD,
+            "ICMPGT" => Opcode::ICMPGT,
+            "RETURN" => Opcode::RETURN,
+            "INVOKE" if is_identifier(operand) => {
+                if self.program.function_index(operand).is_none() {
+                    self.undefined.insert(operand.to_string(), line.number);
+                }
+                Opcode::INVOKE(self.program.declare_function(operand))
+            }
+            "INVOKE_NATIVE" if is_identifier(operand) => Opcode::INVOKE_NATIVE(self.program.declare_native(operand)),
+            "INVOKE" | "INVOKE_NATIVE" => return Err(line.error(format!("Invalid function name `{}`", operand))),
+            "LDC" => match operand.strip_prefix('#') {
+                Some(index) => Opcode::LDC(parse_number(line, index)?),
+                None => Opcode::LDC(self.program.pool.append(parse_literal(line, operand)?)),
+            },
+            "LLOAD" => Opcode::LLOAD(parse_number(line, operand)?),
+            "LSTORE" => Opcode::LSTORE(parse_number(line, operand)?),
+            "IPUSH" => Opcode::IPUSH(parse_number(line, operand)?),
+            "JUMP" | "JUMPNOTIF" => {
+                let target = match operand.parse::<usize>() {
+                    Ok(target) => target,
+                    Err(_) if is_identifier(operand) => {
+                        function.fixups.push((offset, operand.to_string(), line.number));
+                        0
+                    }
+                    Err(_) => return Err(line.error(format!("Invalid jump target `{}`", operand))),
+                };
+                if mnemonic == "JUMP" {
+                    Opcode::JUMP(target)
+                } else {
+                    Opcode::JUMPNOTIF(target)
+                }
+            }
+            _ => return Err(line.error(format!("Unknown opcode `{}`", mnemonic))),
+        };
+        let takes_operand = !matches!(opcode, Opcode::NOP | Opcode::IADD | Opcode::ICMPGT | Opcode::RETURN);
+        if !takes_operand && !operand.is_empty() {
+            return Err(line.error(format!("`{}` doesn't take an operand", mnemonic)));
+        }
+        function.code.push(opcode);
+        Ok(())
+    }
+}
+
+/// Splits off the first whitespace separated word
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+/// Removes a `;` comment, unless the `;` is part of a string literal
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, chr) in line.char_indices() {
+        match chr {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(chr) if chr.is_alphabetic() || chr == '_')
+        && chars.all(|chr| chr.is_alphanumeric() || chr == '_')
+}
+
+fn parse_number<T: std::str::FromStr>(line: &Line, text: &str) -> Result<T, AssemblerError> {
+    text.parse::<T>().map_err(|_| line.error(format!("Invalid operand `{}`", text)))
+}
+
+fn parse_literal(line: &Line, text: &str) -> Result<Object, AssemblerError> {
+    match text {
+        "true" => Ok(Object::Boolean(true)),
+        "false" => Ok(Object::Boolean(false)),
+        _ if text.starts_with('"') => Ok(Object::String(parse_string(line, text)?)),
+        _ => Ok(Object::Integer(parse_number(line, text)?)),
+    }
+}
+
+/// Reads a list like `[1, "a", [true]]`, the way `Object` displays them
+fn parse_list(line: &Line, text: &str) -> Result<Object, AssemblerError> {
+    match parse_element(line, text)? {
+        (list @ Object::List(_), "") => Ok(list),
+        _ => Err(line.error(format!("Invalid list literal {}", text))),
+    }
+}
+
+/// The element at the start of `text` and what follows it
+fn parse_element<'a>(line: &Line, text: &'a str) -> Result<(Object, &'a str), AssemblerError> {
+    let text = text.trim_start();
+    let Some(mut rest) = text.strip_prefix('[') else {
+        let end = literal_end(text);
+        return Ok((parse_literal(line, &text[..end])?, text[end..].trim_start()));
+    };
+    let mut objects = vec![];
+    if let Some(rest) = rest.trim_start().strip_prefix(']') {
+        return Ok((Object::List(objects), rest.trim_start()));
+    }
+    loop {
+        let (object, remaining) = parse_element(line, rest)?;
+        objects.push(object);
+        if let Some(remaining) = remaining.strip_prefix(',') {
+            rest = remaining;
+        } else if let Some(remaining) = remaining.strip_prefix(']') {
+            return Ok((Object::List(objects), remaining.trim_start()));
+        } else {
+            return Err(line.error(format!("Invalid list literal {}", text)));
+        }
+    }
+}
+
+/// Where the literal at the start of `text` ends, a string ends at its closing quote
+fn literal_end(text: &str) -> usize {
+    if !text.starts_with('"') {
+        return text.find(|chr: char| chr == ',' || chr == ']' || chr.is_whitespace()).unwrap_or(text.len());
+    }
+    let mut escaped = false;
+    for (index, chr) in text.char_indices().skip(1) {
+        match chr {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return index + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Reads a double quoted string with the escapes `disassemble` may produce
+fn parse_string(line: &Line, text: &str) -> Result<String, AssemblerError> {
+    let invalid = || line.error(format!("Invalid string literal {}", text));
+    let inner = text.strip_prefix('"').and_then(|text| text.strip_suffix('"')).ok_or_else(invalid)?;
+    let mut string = String::new();
+    let mut chars = inner.chars();
+    while let Some(chr) = chars.next() {
+        if chr == '"' {
+            return Err(invalid());
+        }
+        if chr != '\\' {
+            string.push(chr);
+            continue;
+        }
+        match chars.next().ok_or_else(invalid)? {
+            'n' => string.push('\n'),
+            't' => string.push('\t'),
+            'r' => string.push('\r'),
+            '0' => string.push('\0'),
+            '\\' => string.push('\\'),
+            '"' => string.push('"'),
+            '\'' => string.push('\''),
+            'u' => {
+                let rest = chars.as_str();
+                let code = rest.strip_prefix('{').and_then(|rest| rest.split_once('}')).ok_or_else(invalid)?.0;
+                let chr = u32::from_str_radix(code, 16).ok().and_then(char::from_u32).ok_or_else(invalid)?;
+                string.push(chr);
+                chars = rest[code.len() + 2..].chars();
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use crate::disassembler::disassemble;
+    use crate::Object;
+
+    const SOURCE: &str = r#"
+const str "hello"
+const #1 int 7
+
+fn twice arity=1 locals=2
+    LLOAD 0
+    LLOAD 0
+    IADD
+    LSTORE 1
+    LLOAD 1
+    RETURN
+end
+
+fn main arity=0 locals=0
+    LDC #0
+    INVOKE_NATIVE write
+    LDC #1
+    INVOKE twice
+    IPUSH 20
+    ICMPGT
+    JUMPNOTIF small
+    NOP
+small:
+    RETURN
+end
+"#;
+
+    /// Assembling a listing gives back the same program
+    fn assert_round_trip(source: &str) {
+        let listing = disassemble(&assemble(source).unwrap());
+        let reassembled = assemble(&listing).unwrap_or_else(|err| panic!("{}\n{}", err, listing));
+        assert_eq!(disassemble(&reassembled), listing);
+    }
+
+    fn error(source: &str) -> (usize, String) {
+        let err = assemble(source).unwrap_err();
+        (err.line, err.message)
+    }
+
+    #[test]
+    fn listings_round_trip() {
+        assert_round_trip(SOURCE);
+        assert_round_trip(include_str!("../examples/fib.basm"));
+    }
+
+    #[test]
+    fn calls_keep_their_targets() {
+        let program = assemble(SOURCE).unwrap();
+        assert_eq!(program.function_index("twice"), Some(0));
+        assert_eq!(program.natives, ["write"]);
+    }
+
+    #[test]
+    fn rejects_calls_to_undefined_functions() {
+        let (line, message) = error("fn main arity=0 locals=0\n    INVOKE nope\n    RETURN\nend\n");
+        assert_eq!(line, 2);
+        assert_eq!(message, "Call to undefined function `nope`, natives are called with `INVOKE_NATIVE`");
+    }
+
+    #[test]
+    fn rejects_bad_operands() {
+        let (line, message) = error("fn main arity=0 locals=0\n    IPUSH 1\n    IPUSH one\n    RETURN\nend\n");
+        assert_eq!((line, message.as_str()), (3, "Invalid operand `one`"));
+        let (line, message) = error("fn main arity=0 locals=0\n    RETURN 1\nend\n");
+        assert_eq!((line, message.as_str()), (2, "`RETURN` doesn't take an operand"));
+    }
+
+    #[test]
+    fn list_constants_round_trip() {
+        let source =
+            "const list [1, \"a, \\\"b]\", [true, []]]\nconst list []\n\nfn main arity=0 locals=0\n    RETURN\nend\n";
+        let program = assemble(source).unwrap();
+        let inner = Object::List(vec![Object::Boolean(true), Object::List(vec![])]);
+        let list = Object::List(vec![Object::Integer(1), Object::String("a, \"b]".to_string()), inner]);
+        assert_eq!(program.pool.entries, [list, Object::List(vec![])]);
+        assert_round_trip(source);
+        let (line, message) = error("const list [1, 2\n");
+        assert_eq!((line, message.as_str()), (1, "Invalid list literal [1, 2"));
+        let (line, message) = error("const list [1] 2\n");
+        assert_eq!((line, message.as_str()), (1, "Invalid list literal [1] 2"));
+    }
+
+    #[test]
+    fn rejects_unknown_mnemonics() {
+        let (line, message) = error("\nfn main arity=0 locals=0\n    PUSH 1\n    RETURN\nend\n");
+        assert_eq!((line, message.as_str()), (3, "Unknown opcode `PUSH`"));
+    }
+
+    #[test]
+    fn rejects_undefined_labels() {
+        let (line, message) = error("fn main arity=0 locals=0\n    JUMP nowhere\n    RETURN\nend\n");
+        assert_eq!((line, message.as_str()), (2, "Undefined label `nowhere`"));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::Program;
+use std::fmt;
+
+/// Every compiled program starts with these bytes
+pub const MAGIC: &[u8; 4] = b"BUG\0";
+/// Bumped whenever the serialized `Program` changes shape, binaries of other versions are rejected
+pub const FORMAT_VERSION: u16 = 4;
+
+/// Serializes `program` behind a header:
+///
+/// ```text
+/// magic            4 bytes  "BUG\0"
+/// format version   u16
+/// compiler version u8 length followed by that many bytes of UTF-8
+/// payload length   u64
+/// checksum         u32, FNV-1a of the payload
+/// payload          bincode encoded `Program`
+/// ```
+///
+/// Integers are little endian.
+pub fn encode(program: &Program) -> Result<Vec<u8>, BinaryError> {
+    let payload = bincode::serialize(program).map_err(|err| BinaryError::Malformed(err.to_string()))?;
+    let compiler_version = env!("CARGO_PKG_VERSION").as_bytes();
+    let mut bytes: Vec<u8> = Vec::with_capacity(payload.len() + 32);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.push(compiler_version.len() as u8);
+    bytes.extend_from_slice(compiler_version);
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+/// Reads back a program written by `encode`
+pub fn decode(bytes: &[u8]) -> Result<Program, BinaryError> {
+    let mut reader = Reader { bytes, cursor: 0 };
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(BinaryError::NotAProgram);
+    }
+    let version = u16::from_le_bytes(reader.array()?);
+    let compiler_length = reader.take(1)?[0] as usize;
+    let compiler_version = String::from_utf8_lossy(reader.take(compiler_length)?).to_string();
+    if version != FORMAT_VERSION {
+        return Err(BinaryError::IncompatibleVersion { version, compiler_version });
+    }
+    let length = u64::from_le_bytes(reader.array()?) as usize;
+    let expected_checksum = u32::from_le_bytes(reader.array()?);
+    let payload = &bytes[reader.cursor..];
+    if payload.len() < length {
+        return Err(BinaryError::Truncated { expected: length, found: payload.len() });
+    }
+    if payload.len() > length {
+        return Err(BinaryError::TrailingBytes(payload.len() - length));
+    }
+    if checksum(payload) != expected_checksum {
+        return Err(BinaryError::ChecksumMismatch);
+    }
+    bincode::deserialize(payload).map_err(|err| BinaryError::Malformed(err.to_string()))
+}
+
+#[derive(Debug)]
+pub enum BinaryError {
+    /// The file doesn't start with `MAGIC`
+    NotAProgram,
+    /// The file ends in the middle of the header or of the payload, sizes are in bytes
+    Truncated {
+        expected: usize,
+        found: usize,
+    },
+    IncompatibleVersion {
+        version: u16,
+        compiler_version: String,
+    },
+    TrailingBytes(usize),
+    ChecksumMismatch,
+    /// The checksum matched but the payload couldn't be (de)serialized
+    Malformed(String),
+}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAProgram => write!(f, "Not a compiled bug program, the header is missing"),
+            Self::Truncated { expected, found } => {
+                write!(f, "The file is truncated, expecting {} bytes but only {} are left", expected, found)
+            }
+            Self::IncompatibleVersion { version, compiler_version } => write!(
+                f,
+                "Compiled by bug {} in format version {}, but this is bug {} which reads version {}, recompile the program",
+                compiler_version,
+                version,
+                env!("CARGO_PKG_VERSION"),
+                FORMAT_VERSION
+            ),
+            Self::TrailingBytes(count) => write!(f, "The file has {} unexpected bytes after the program", count),
+            Self::ChecksumMismatch => write!(f, "The checksum doesn't match, the file is corrupted"),
+            Self::Malformed(reason) => write!(f, "The program is malformed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for BinaryError {}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], BinaryError> {
+        let left = self.bytes.len() - self.cursor;
+        if left < count {
+            return Err(BinaryError::Truncated { expected: count, found: left });
+        }
+        self.cursor += count;
+        Ok(&self.bytes[self.cursor - count..self.cursor])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BinaryError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+/// 32 bit FNV-1a
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x01000193))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, decode, encode, BinaryError, FORMAT_VERSION, MAGIC};
+    use crate::assembler::assemble;
+    use crate::disassembler::disassemble;
+
+    fn encoded() -> Vec<u8> {
+        let program = assemble(
+            "const str \"hi\"\nfn main arity=0 locals=0\n    LDC #0\n    INVOKE_NATIVE write\n    RETURN\nend\n",
+        );
+        encode(&program.unwrap()).unwrap()
+    }
+
+    /// Where the payload starts, after the checksum
+    fn payload_start(bytes: &[u8]) -> usize {
+        MAGIC.len() + 2 + 1 + bytes[MAGIC.len() + 2] as usize + 8 + 4
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let program = assemble("fn main arity=0 locals=1\n    IPUSH 1\n    LSTORE 0\n    RETURN\nend\n").unwrap();
+        let decoded = decode(&encode(&program).unwrap()).unwrap();
+        assert_eq!(disassemble(&decoded), disassemble(&program));
+    }
+
+    #[test]
+    fn rejects_files_without_the_magic() {
+        assert!(matches!(decode(b"fn main() void -> 1;"), Err(BinaryError::NotAProgram)));
+        assert!(matches!(decode(b"BU"), Err(BinaryError::NotAProgram)));
+        let mut bytes = encoded();
+        bytes[0] = b'b';
+        assert!(matches!(decode(&bytes), Err(BinaryError::NotAProgram)));
+    }
+
+    #[test]
+    fn rejects_other_format_versions() {
+        let mut bytes = encoded();
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        match decode(&bytes) {
+            Err(BinaryError::IncompatibleVersion { version, compiler_version }) => {
+                assert_eq!(version, FORMAT_VERSION + 1);
+                assert_eq!(compiler_version, env!("CARGO_PKG_VERSION"));
+            }
+            result => panic!("unexpected {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = encoded();
+        assert!(matches!(decode(&bytes[..MAGIC.len() + 1]), Err(BinaryError::Truncated { expected: 2, found: 1 })));
+        let payload = bytes.len() - payload_start(&bytes);
+        let result = decode(&bytes[..bytes.len() - 1]);
+        assert!(
+            matches!(result, Err(BinaryError::Truncated { expected, found }) if expected == payload && found == payload - 1)
+        );
+        let message = format!("The file is truncated, expecting {} bytes but only {} are left", payload, payload - 1);
+        assert_eq!(result.unwrap_err().to_string(), message);
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let mut bytes = encoded();
+        bytes.extend_from_slice(&[0, 0]);
+        assert!(matches!(decode(&bytes), Err(BinaryError::TrailingBytes(2))));
+    }
+
+    #[test]
+    fn rejects_corrupted_payloads() {
+        let mut bytes = encoded();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert!(matches!(decode(&bytes), Err(BinaryError::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn reports_malformed_payloads_with_a_valid_checksum() {
+        let mut bytes = encoded();
+        let start = payload_start(&bytes);
+        bytes[start..].fill(0xff);
+        let sum = checksum(&bytes[start..]);
+        bytes[start - 4..start].copy_from_slice(&sum.to_le_bytes());
+        assert!(matches!(decode(&bytes), Err(BinaryError::Malformed(_))));
+    }
+}
@@ -1,6 +1,6 @@
 use std::io::Seek;
 
-use super::span::Span;
+use bug::span::Span;
 use bug::Type;
 
 pub type Ast = Vec<Statement>;
@@ -44,7 +44,7 @@ pub struct StatementFunction {
     pub return_type: Type,
     pub body: StatementBlock,
     pub parameters: Parameters,
-    pub _signature_span: Span,
+    pub signature_span: Span,
 }
 
 impl StatementFunction {
@@ -55,7 +55,7 @@ impl StatementFunction {
         body: StatementBlock,
         signature_span: Span,
     ) -> Self {
-        Self { identifier, parameters, return_type, body, _signature_span: signature_span }
+        Self { identifier, parameters, return_type, body, signature_span }
     }
 }
 
@@ -84,7 +84,7 @@ impl Parameter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct StatementBlock {
     pub span: Span,
     pub statements: Vec<Statement>,
@@ -96,7 +96,7 @@ impl StatementBlock {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Parameters {
     pub parameters: Vec<Parameter>,
     pub span: Span,
@@ -160,7 +160,6 @@ impl ExpressionCall {
 #[derive(Debug)]
 pub enum BinaryOperator {
     Plus,
-    Minus,
     GratherThan,
 }
 
@@ -181,7 +180,6 @@ impl std::fmt::Display for BinaryOperator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Plus => write!(f, "+"),
-            Self::Minus => write!(f, "-"),
             Self::GratherThan => write!(f, ">"),
         }
     }
@@ -1,7 +1,7 @@
-use super::highlighter::highlight_error;
-use super::span::Span;
-use super::{ast::*, span};
-use bug::stdlib::NativeFn;
+use super::ast::*;
+use bug::highlighter::highlight_error;
+use bug::span::Span;
+use bug::stdlib::format::{self, Placeholder};
 use bug::{FunctionPrototype, Type};
 use std::collections::HashMap;
 
@@ -10,11 +10,48 @@ pub struct Checker<'a> {
     file_content: &'a str,
     ctx: Context,
     diagnostics: Diagnostics,
+    snapshots: Vec<StackSnapshot>,
+    /// The string literal on top of the stack, when the last expression pushed one
+    literal: Option<(String, Span)>,
 }
 
 impl<'a> Checker<'a> {
-    pub fn new(file_path: &'a str, file_content: &'a str, natives: HashMap<String, NativeFn>) -> Self {
-        Self { file_path, file_content, ctx: Context::new(natives), diagnostics: Diagnostics::new() }
+    pub fn new(file_path: &'a str, file_content: &'a str, natives: HashMap<String, FunctionPrototype>) -> Self {
+        Self {
+            file_path,
+            file_content,
+            ctx: Context::new(natives),
+            diagnostics: Diagnostics::new(),
+            snapshots: vec![],
+            literal: None,
+        }
+    }
+
+    /// Makes a function defined elsewhere callable from the checked code
+    pub fn declare_function(&mut self, name: String, prototype: FunctionPrototype) {
+        self.ctx.declare(name, Symbol::Function(prototype));
+    }
+
+    /// Checks `statements` as the body of a function without parameters and returns the types left on its stack
+    pub fn check_block(&mut self, statements: &mut [Statement]) -> Result<Vec<Type>, &Diagnostics> {
+        self.ctx.enter_scope(ScopeType::Function);
+        for statement in statements {
+            if let Err(err) = self.check_statement(statement) {
+                self.diagnostics.diagnostics.push(err);
+                break;
+            }
+        }
+        let types = self.ctx.stack_types();
+        self.ctx.leave_scope();
+        if self.diagnostics.diagnostics.is_empty() {
+            Ok(types)
+        } else {
+            Err(&self.diagnostics)
+        }
+    }
+
+    pub fn snapshots(&self) -> &[StackSnapshot] {
+        &self.snapshots
     }
 
     pub fn check(&mut self, ast: &'a mut Ast) -> Option<&Diagnostics> {
@@ -30,7 +67,10 @@ impl<'a> Checker<'a> {
         }
     }
 
-    fn check_statement(&mut self, statement: &mut Statement) -> Result<(), String> {
+    fn check_statement(&mut self, statement: &mut Statement) -> Result<(), Diagnostic> {
+        if !matches!(statement, Statement::Expression(_)) {
+            self.literal = None;
+        }
         match statement {
             Statement::Function(function) => Ok(self.check_statement_function(function)?),
             Statement::Variable(variable) => Ok(self.check_statement_vardecl(variable)?),
@@ -39,17 +79,24 @@ impl<'a> Checker<'a> {
         }
     }
 
-    fn check_statement_function(&mut self, f: &mut StatementFunction) -> Result<(), String> {
+    fn check_statement_function(&mut self, f: &mut StatementFunction) -> Result<(), Diagnostic> {
         let name = f.identifier.label.clone();
-        if let Some(_) = self.ctx.lookup_locally(&name) {
+        if self.ctx.lookup_locally(&name).is_some() {
             return Err(self.error_name_already_used(&name, &f.identifier.span));
         }
         let param_types: Vec<Type> = f.parameters.parameters.iter().map(|p| p.typ.clone()).collect();
+        if name == "main" && !is_main_signature(&param_types, &f.return_type) {
+            let err = self.error(
+                "`main` takes no parameters or the arguments as `[str]`, and returns `void` or an `int` exit code",
+                &f.signature_span,
+            );
+            self.diagnostics.diagnostics.push(err);
+        }
         let prototype = FunctionPrototype::new(param_types.len(), f.return_type.clone(), param_types);
         self.ctx.declare(name.clone(), Symbol::Function(prototype));
         self.ctx.enter_scope(ScopeType::Function);
         for param in &f.parameters.parameters {
-            if let Some(_) = self.ctx.lookup_locally(&param.identifier.label) {
+            if self.ctx.lookup_locally(&param.identifier.label).is_some() {
                 return Err(self.error_name_already_used(&param.identifier.label, &param.identifier.span));
             }
             self.ctx.declare(param.identifier.label.clone(), Symbol::Variable(Variable::new(param.typ.clone())))
@@ -66,9 +113,9 @@ impl<'a> Checker<'a> {
         Ok(())
     }
 
-    fn check_statement_vardecl(&mut self, v: &mut VariableDeclaration) -> Result<(), String> {
+    fn check_statement_vardecl(&mut self, v: &mut VariableDeclaration) -> Result<(), Diagnostic> {
         let name = v.identifier.label.clone();
-        if let Some(_) = self.ctx.lookup_locally(&name) {
+        if self.ctx.lookup_locally(&name).is_some() {
             return Err(self.error_name_already_used(&name, &v.identifier.span));
         }
         self.ctx.declare(name, Symbol::Variable(Variable::new(v.typ.clone())));
@@ -76,15 +123,15 @@ impl<'a> Checker<'a> {
         Ok(())
     }
 
-    fn check_statement_assignment(&mut self, assignment: &mut StatementAssignment) -> Result<(), String> {
+    fn check_statement_assignment(&mut self, assignment: &mut StatementAssignment) -> Result<(), Diagnostic> {
         let target_name = match self.ctx.scopes[self.ctx.scope_pointer].assignments.last() {
             Some(name) => name.clone(),
-            None => return Err("".to_string()),
+            None => return Err(self.error_assign_no_target(&assignment.span)),
         };
 
         let (val_typ, val_span) = match self.ctx.pop() {
             Some((typ, span)) => (typ, span),
-            None => return Err("".to_owned()),
+            None => return Err(self.error_assign_missing_value(&target_name, &assignment.span)),
         };
 
         if let Symbol::Variable(v) = self.ctx.lookup_locally(&target_name).unwrap() {
@@ -100,17 +147,31 @@ impl<'a> Checker<'a> {
         Ok(())
     }
 
-    fn check_statement_expression(&mut self, expression: &mut StatementExpression) -> Result<(), String> {
-        match expression {
-            StatementExpression::Call(call) => Ok(self.check_expression_call(call)?),
-            StatementExpression::Binary(binary) => Ok(self.check_expression_binary(binary)?),
-            StatementExpression::Literal(literal) => Ok(self.check_expression_literal(literal)?),
-            StatementExpression::Identifier(identifier) => Ok(self.check_expression_identifier(identifier)?),
+    fn check_statement_expression(&mut self, expression: &mut StatementExpression) -> Result<(), Diagnostic> {
+        self.check_expression(expression)?;
+        self.snapshots.push(StackSnapshot::new(expression.get_span(), self.ctx.stack_types()));
+        Ok(())
+    }
+
+    fn check_expression(&mut self, expression: &mut StatementExpression) -> Result<(), Diagnostic> {
+        let literal = match expression {
+            StatementExpression::Literal(ExpressionLiteral::String(string)) => {
+                Some((string.inner.clone(), string.span.clone()))
+            }
+            _ => None,
+        };
+        let checked = match expression {
+            StatementExpression::Call(call) => self.check_expression_call(call),
+            StatementExpression::Binary(binary) => self.check_expression_binary(binary),
+            StatementExpression::Literal(literal) => self.check_expression_literal(literal),
+            StatementExpression::Identifier(identifier) => self.check_expression_identifier(identifier),
             StatementExpression::Ternary(ternary) => self.check_expression_ternary(ternary),
-        }
+        };
+        self.literal = literal;
+        checked
     }
 
-    fn check_expression_ternary(&mut self, ternary: &mut ExpressionTernary) -> Result<(), String> {
+    fn check_expression_ternary(&mut self, ternary: &mut ExpressionTernary) -> Result<(), Diagnostic> {
         if self.ctx.stack_depth() < 1 {
             return Err(self.error_miss_ternary_cond(&ternary.span));
         }
@@ -129,7 +190,7 @@ impl<'a> Checker<'a> {
         Ok(())
     }
 
-    fn check_expression_identifier(&mut self, identifier: &Identifier) -> Result<(), String> {
+    fn check_expression_identifier(&mut self, identifier: &Identifier) -> Result<(), Diagnostic> {
         let symbol = match self.ctx.lookup(&identifier.label) {
             Some(symbol) => symbol,
             None => {
@@ -138,19 +199,22 @@ impl<'a> Checker<'a> {
         };
         match symbol {
             Symbol::Variable(v) => Ok(self.ctx.push(v.typ.clone(), identifier.span.clone())),
-            Symbol::Function(_) => unimplemented!(),
+            Symbol::Function(_) => Err(self.error_function_as_value(&identifier.label, &identifier.span)),
         }
     }
 
-    fn check_expression_call(&mut self, call: &ExpressionCall) -> Result<(), String> {
+    fn check_expression_call(&mut self, call: &ExpressionCall) -> Result<(), Diagnostic> {
         let callee = self.ctx.lookup(&call.identifier.label);
         if callee.is_none() {
             return Err(self.error_name_not_declared(&call.identifier.label, &call.identifier.span));
         }
         let callee = match callee.unwrap() {
             Symbol::Function(f) => f.clone(),
-            _ => todo!("{} is not callable", call.identifier.label),
+            _ => return Err(self.error_not_callable(&call.identifier.label, &call.identifier.span)),
         };
+        if callee.template {
+            return self.check_expression_template_call(call, callee);
+        }
         if self.ctx.stack_depth() < callee.arity {
             self.ctx.pop_many(self.ctx.stack_depth());
             return Err(self.error_missing_args(&call.identifier.label, &call.span));
@@ -166,7 +230,34 @@ impl<'a> Checker<'a> {
         Ok(())
     }
 
-    fn check_expression_binary(&mut self, binary: &mut ExpressionBinary) -> Result<(), String> {
+    /// How many values a template native takes depends on its template, so it must be a literal
+    fn check_expression_template_call(
+        &mut self,
+        call: &ExpressionCall,
+        callee: FunctionPrototype,
+    ) -> Result<(), Diagnostic> {
+        let Some((template, template_span)) = self.literal.take() else {
+            return Err(self.error_template_not_literal(&call.identifier.label, &call.span));
+        };
+        let placeholders = format::placeholders(&template).map_err(|message| self.error(&message, &template_span))?;
+        self.ctx.pop();
+        if self.ctx.stack_depth() < placeholders.len() {
+            let found = self.ctx.stack_depth();
+            self.ctx.pop_many(found);
+            return Err(self.error_template_missing_values(placeholders.len(), found, &call.span));
+        }
+        let values = self.ctx.pop_many(placeholders.len());
+        for ((typ, span), placeholder) in values.into_iter().zip(placeholders) {
+            if !placeholder.accepts(&typ) {
+                let err = self.error_placeholder_type(&placeholder, &typ, &span);
+                self.diagnostics.diagnostics.push(err);
+            }
+        }
+        self.ctx.push(callee.return_type, call.span.clone());
+        Ok(())
+    }
+
+    fn check_expression_binary(&mut self, binary: &mut ExpressionBinary) -> Result<(), Diagnostic> {
         if self.ctx.stack_depth() < 2 {
             return Err(self.error_miss_binexpr_args(&binary.operator, &binary.span));
         }
@@ -179,34 +270,26 @@ impl<'a> Checker<'a> {
         binary.operands_types = Some(lhs_type.clone());
         match binary.operator {
             BinaryOperator::Plus => Ok(self.check_binary_plus(lhs_type, rhs_type, span)?),
-            BinaryOperator::Minus => Ok(self.check_binary_minus(lhs_type, rhs_type, span)?),
             BinaryOperator::GratherThan => Ok(self.check_binary_gt(lhs_type, rhs_type, span)?),
         }
     }
 
-    fn check_binary_plus(&mut self, lhs: Type, _rhs: Type, span: Span) -> Result<(), String> {
+    fn check_binary_plus(&mut self, lhs: Type, _rhs: Type, span: Span) -> Result<(), Diagnostic> {
         match lhs {
             Type::Integer => Ok(self.ctx.push(Type::Integer, span)),
-            Type::String => Ok(self.ctx.push(Type::String, span)),
+            Type::String => Err(self.error_string_plus(&span)),
             _ => Err(self.error_invalid_operator_operands(&BinaryOperator::Plus, &lhs, &span)),
         }
     }
 
-    fn check_binary_minus(&mut self, lhs: Type, _rhs: Type, span: Span) -> Result<(), String> {
-        match lhs {
-            Type::Integer => Ok(self.ctx.push(Type::Integer, span)),
-            _ => Err(self.error_invalid_operator_operands(&BinaryOperator::Minus, &lhs, &span)),
-        }
-    }
-
-    fn check_binary_gt(&mut self, lhs: Type, _rhs: Type, span: Span) -> Result<(), String> {
+    fn check_binary_gt(&mut self, lhs: Type, _rhs: Type, span: Span) -> Result<(), Diagnostic> {
         match lhs {
             Type::Integer => Ok(self.ctx.push(Type::Boolean, span)),
             _ => Err(self.error_invalid_operator_operands(&BinaryOperator::GratherThan, &lhs, &span)),
         }
     }
 
-    fn check_expression_literal(&mut self, literal: &ExpressionLiteral) -> Result<(), String> {
+    fn check_expression_literal(&mut self, literal: &ExpressionLiteral) -> Result<(), Diagnostic> {
         match literal {
             ExpressionLiteral::String(string) => Ok(self.ctx.push(Type::String, string.span.clone())),
             ExpressionLiteral::Integer(integer) => Ok(self.ctx.push(Type::Integer, integer.span.clone())),
@@ -231,6 +314,8 @@ impl Variable {
 }
 
 enum ScopeType {
+    /// Below the global scope, so that functions can shadow natives
+    Natives,
     Global,
     Function,
 }
@@ -252,18 +337,24 @@ impl Scope {
     }
 }
 
+fn is_main_signature(parameters_types: &[Type], return_type: &Type) -> bool {
+    let arguments = Type::List(Box::new(Type::String));
+    let parameters = parameters_types.is_empty() || parameters_types == [arguments];
+    parameters && matches!(return_type, Type::Void | Type::Integer)
+}
+
 struct Context {
     scope_pointer: usize,
     scopes: Vec<Scope>,
 }
 
 impl Context {
-    fn new(natives: HashMap<String, NativeFn>) -> Self {
+    fn new(natives: HashMap<String, FunctionPrototype>) -> Self {
         let mut table: HashMap<String, Symbol> = HashMap::new();
-        for (name, f) in natives {
-            table.insert(name, Symbol::Function(f.prototype));
+        for (name, prototype) in natives {
+            table.insert(name, Symbol::Function(prototype));
         }
-        Self { scope_pointer: 0, scopes: vec![Scope::from(ScopeType::Global, table)] }
+        Self { scope_pointer: 1, scopes: vec![Scope::from(ScopeType::Natives, table), Scope::new(ScopeType::Global)] }
     }
 
     fn lookup(&self, name: &str) -> Option<&Symbol> {
@@ -297,6 +388,10 @@ impl Context {
         self.scopes[self.scope_pointer].stack.len()
     }
 
+    fn stack_types(&self) -> Vec<Type> {
+        self.scopes[self.scope_pointer].stack.iter().map(|(typ, _)| typ.clone()).collect()
+    }
+
     fn pop(&mut self) -> Option<(Type, Span)> {
         self.scopes[self.scope_pointer].stack.pop().clone()
     }
@@ -311,18 +406,33 @@ impl Context {
         for _ in 0..count {
             types.push(self.scopes[self.scope_pointer].stack.pop().unwrap())
         }
+        // In the order they were pushed, like the parameters they are checked against
+        types.reverse();
         types
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    rendered: String,
+}
+
+impl Diagnostic {
+    fn new(message: String, span: Span, rendered: String) -> Self {
+        Self { message, span, rendered }
+    }
+}
+
 pub struct Diagnostics {
-    diagnostics: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 impl core::fmt::Display for Diagnostics {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for diagnostic in &self.diagnostics {
-            write!(f, "{}", diagnostic)?;
+            write!(f, "{}", diagnostic.rendered)?;
         }
         Ok(())
     }
@@ -334,16 +444,29 @@ impl Diagnostics {
     }
 }
 
+/// The types on the stack right after the expression at `span` was checked
+#[derive(Debug, Clone)]
+pub struct StackSnapshot {
+    pub span: Span,
+    pub stack: Vec<Type>,
+}
+
+impl StackSnapshot {
+    fn new(span: Span, stack: Vec<Type>) -> Self {
+        Self { span, stack }
+    }
+}
+
 impl<'a> Checker<'a> {
-    fn error_name_already_used(&mut self, name: &str, span: &Span) -> String {
+    fn error_name_already_used(&mut self, name: &str, span: &Span) -> Diagnostic {
         self.error(&format!("Name `{}` is already used", name), span)
     }
 
-    fn error_return_type(&mut self, name: &str, span: &Span, expected: &Type, provided: &Type) -> String {
+    fn error_return_type(&mut self, name: &str, span: &Span, expected: &Type, provided: &Type) -> Diagnostic {
         self.error(&format!("Function `{}` returns `{}` but got `{}` ", name, expected, provided), span)
     }
 
-    fn error_assign_wrong_type(&mut self, var_name: &str, expected: &Type, provided: &Type, span: &Span) -> String {
+    fn error_assign_wrong_type(&mut self, var_name: &str, expected: &Type, provided: &Type, span: &Span) -> Diagnostic {
         self.error(
             &format!(
                 "Variable `{}` expects value of type `{}` but value of type `{}` was provided",
@@ -353,62 +476,95 @@ impl<'a> Checker<'a> {
         )
     }
 
-    fn error_name_not_declared(&mut self, name: &str, span: &Span) -> String {
+    fn error_name_not_declared(&mut self, name: &str, span: &Span) -> Diagnostic {
         self.error(&format!("Name `{}` is not declared", name), span)
     }
 
-    fn error_missing_args(&mut self, name: &str, span: &Span) -> String {
+    fn error_assign_no_target(&mut self, span: &Span) -> Diagnostic {
+        self.error("There is no declared variable to assign to", span)
+    }
+
+    fn error_assign_missing_value(&mut self, var_name: &str, span: &Span) -> Diagnostic {
+        self.error(&format!("Missing value to assign to variable `{}`", var_name), span)
+    }
+
+    fn error_function_as_value(&mut self, name: &str, span: &Span) -> Diagnostic {
+        self.error(&format!("`{}` is a function, call it with `@{}`", name, name), span)
+    }
+
+    fn error_not_callable(&mut self, name: &str, span: &Span) -> Diagnostic {
+        self.error(&format!("`{}` is not callable", name), span)
+    }
+
+    fn error_missing_args(&mut self, name: &str, span: &Span) -> Diagnostic {
         self.error(&format!("Missing arguments calling `{}`", name), span)
     }
 
-    fn error_arg_type_no_match(&mut self, expected: &Type, provided: &Type, span: &Span) -> String {
+    fn error_arg_type_no_match(&mut self, expected: &Type, provided: &Type, span: &Span) -> Diagnostic {
         self.error(
             &format!("Arguement of type `{}` is not assignable to parameter of type `{}`", provided, expected),
             span,
         )
     }
 
+    fn error_template_not_literal(&mut self, name: &str, span: &Span) -> Diagnostic {
+        self.error(&format!("The template of `{}` must be a string literal right before the call", name), span)
+    }
+
+    fn error_template_missing_values(&mut self, expected: usize, found: usize, span: &Span) -> Diagnostic {
+        let message = format!("The template has {} placeholders but there are {} values on the stack", expected, found);
+        self.error(&message, span)
+    }
+
+    fn error_placeholder_type(&mut self, placeholder: &Placeholder, provided: &Type, span: &Span) -> Diagnostic {
+        self.error(&format!("Can't fill `{}` with a value of type `{}`", placeholder, provided), span)
+    }
+
     fn error_binexpr_types_no_match(
         &mut self,
         op: &BinaryOperator,
         lhs_type: &Type,
         rhs_type: &Type,
         span: &Span,
-    ) -> String {
+    ) -> Diagnostic {
         self.error(&format!("Operator `{}` doesn't apply to types `{}` and `{}`", op, lhs_type, rhs_type), span)
     }
 
-    fn error_miss_binexpr_args(&mut self, op: &BinaryOperator, span: &Span) -> String {
+    fn error_miss_binexpr_args(&mut self, op: &BinaryOperator, span: &Span) -> Diagnostic {
         self.error(&format!("Missing arguments for `{}` operator", op), span)
     }
 
-    fn error_invalid_operator_operands(&mut self, op: &BinaryOperator, typ: &Type, span: &Span) -> String {
+    fn error_invalid_operator_operands(&mut self, op: &BinaryOperator, typ: &Type, span: &Span) -> Diagnostic {
         self.error(&format!("Operator `{}` doesn't apply to values of type `{}`", op, typ), span)
     }
 
-    fn error_miss_ternary_cond(&mut self, span: &Span) -> String {
+    fn error_string_plus(&mut self, span: &Span) -> Diagnostic {
+        self.error("Operator `+` doesn't apply to values of type `str`, join strings with `@concat`", span)
+    }
+
+    fn error_miss_ternary_cond(&mut self, span: &Span) -> Diagnostic {
         self.error("Missing condition for the ternary operator", span)
     }
 
-    fn error_unexpected_type(&mut self, expected: &Type, provided: &Type, span: &Span) -> String {
+    fn error_unexpected_type(&mut self, expected: &Type, provided: &Type, span: &Span) -> Diagnostic {
         self.error(&format!("Expecting value of type `{}` but got `{}`", expected, provided), span)
     }
 
-    fn error_ternary_arms_no_match(&mut self, consq_type: &Type, alt_type: &Type, span: &Span) -> String {
+    fn error_ternary_arms_no_match(&mut self, consq_type: &Type, alt_type: &Type, span: &Span) -> Diagnostic {
         let mut message = String::new();
         message.push_str("`?` operator arms have different types\n");
         message.push_str(&format!("\tFirst arm returns `{}` and alternative returns `{}`", consq_type, alt_type));
         self.error(&message, span)
     }
 
-    fn error(&mut self, message: &str, span: &Span) -> String {
+    fn error(&mut self, message: &str, span: &Span) -> Diagnostic {
         let mut error = String::new();
-        error.push_str(&self.error_header(&span));
+        error.push_str(&self.error_header(span));
         error.push_str(message);
         error.push_str("\n\n");
-        error.push_str(&highlight_error(&self.file_content, span.start, span.end));
+        error.push_str(&highlight_error(self.file_content, span.start, span.end));
         error.push('\n');
-        error
+        Diagnostic::new(message.to_string(), span.clone(), error)
     }
 
     fn error_header(&self, span: &Span) -> String {
@@ -418,3 +574,70 @@ impl<'a> Checker<'a> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::try_compile;
+    use bug::stdlib::list_natives;
+    use bug::vm::Engine;
+    use bug::Object;
+
+    fn run(source: &str) -> Option<Object> {
+        let program = try_compile("test.bug", source).unwrap();
+        Engine::bootstrap(program, list_natives()).run().unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        try_compile("test.bug", source).unwrap_err()
+    }
+
+    #[test]
+    fn there_is_no_subtraction() {
+        assert!(error("fn main() int -> 2 1 -;\n").contains("Unexpected expression"));
+    }
+
+    #[test]
+    fn templates_are_checked_when_compiling() {
+        let source = "fn main() void -> 1 \"{} and {}\" @printf;\n";
+        assert!(error(source).contains("The template has 2 placeholders but there are 1 values on the stack"));
+        let source = "fn main() void -> 1 \"{:5\" @printf;\n";
+        assert!(error(source).contains("A `{` is never closed, write `{{` for a literal brace"));
+        let source = "fn main() void -> \"ab\" \"{:05}\" @printf;\n";
+        assert!(error(source).contains("Can't fill `{:05}` with a value of type `str`"));
+        let source = "fn main() void -> \"a b\" \" \" @split \"{}\" @printf;\n";
+        assert!(error(source).contains("Can't fill `{}` with a value of type `[str]`"));
+        let source = "fn main() void -> \"{}\" \"{}\" @format @printf;\n";
+        assert!(error(source).contains("The template of `printf` must be a string literal right before the call"));
+    }
+
+    #[test]
+    fn templates_fill_their_placeholders() {
+        let source = "fn fill() str -> \"ab\" 7 \"{{{:<3}|{:03}}}\" @format;\nfn main() void -> ;\n";
+        let program = try_compile("test.bug", source).unwrap();
+        let filled = Engine::bootstrap(program, list_natives()).call::<(), String>("fill", ());
+        assert_eq!(filled, Ok("{ab |007}".to_string()));
+    }
+
+    #[test]
+    fn functions_shadow_natives() {
+        let source = "fn max(int l, int r) int -> l;\nfn main() int -> 1 9 @max;\n";
+        assert_eq!(run(source), Some(Object::Integer(1)));
+        let source = "fn write(str text) int -> text @len;\nfn main() int -> \"quiet\" @write;\n";
+        assert_eq!(run(source), Some(Object::Integer(5)));
+    }
+
+    #[test]
+    fn natives_are_called_before_a_function_shadows_them() {
+        let source = "fn first() int -> 1 9 @max;\nfn max(int l, int r) int -> r;\nfn main() int -> @first 2 @max;\n";
+        assert_eq!(run(source), Some(Object::Integer(2)));
+        let source = "fn first() int -> 1 9 @max;\nfn max(int l, int r) int -> r;\nfn main() int -> @first;\n";
+        assert_eq!(run(source), Some(Object::Integer(9)));
+    }
+
+    #[test]
+    fn functions_still_cant_share_a_name() {
+        let source = "fn max(int l, int r) int -> l;\nfn max(int l, int r) int -> r;\nfn main() void -> ;\n";
+        let errors = error(source);
+        assert!(errors.contains("Name `max` is already used"), "{}", errors);
+    }
+}
@@ -1,4 +1,5 @@
 use super::ast::*;
+use bug::span::Span;
 use bug::*;
 use bytecode::{ByteCodeStream, Opcode};
 use std::collections::HashMap;
@@ -6,20 +7,35 @@ use std::collections::HashMap;
 struct Context {
     code: ByteCodeStream,
     locals: HashMap<String, usize>,
+    /// Source location of every instruction in `code`
+    spans: Vec<Span>,
+    /// Source location of the code being emitted
+    span: Span,
 }
 
 impl Context {
     fn new() -> Self {
-        Context { code: ByteCodeStream::empty(), locals: HashMap::new() }
+        Context { code: ByteCodeStream::empty(), locals: HashMap::new(), spans: vec![], span: Span::default() }
     }
 
     fn push(&mut self, op: Opcode) {
         self.code.push(op);
+        self.spans.push(self.span.clone());
+    }
+
+    /// Names of the locals ordered by index
+    fn local_names(&self) -> Vec<String> {
+        let mut names = vec![String::new(); self.locals.len()];
+        for (name, index) in &self.locals {
+            names[*index] = name.clone();
+        }
+        names
     }
 
     fn reset(&mut self) {
         self.code.code.clear();
         self.locals.clear();
+        self.spans.clear();
     }
 }
 
@@ -29,8 +45,10 @@ pub struct CodeGenerator {
 }
 
 impl CodeGenerator {
-    pub fn setup() -> Self {
-        Self { program: Program::new(), context: Context::new() }
+    pub fn setup(file_name: &str) -> Self {
+        let mut program = Program::new();
+        program.file_name = file_name.to_string();
+        Self { program, context: Context::new() }
     }
 
     pub fn emit(&mut self, ast: Ast) -> Program {
@@ -40,6 +58,22 @@ impl CodeGenerator {
         self.program.clone()
     }
 
+    /// Emits `statements` as the body of a function without parameters, keeping the pool of the program
+    pub fn emit_block(&mut self, statements: Vec<Statement>) -> DefinedFn {
+        self.context.reset();
+        for statement in statements {
+            self.emit_statement(statement);
+        }
+        self.context.push(Opcode::RETURN);
+        DefinedFn::new(0, 0, self.context.code.clone(), self.context.locals.len())
+            .with_spans(self.context.spans.clone())
+            .with_local_names(self.context.local_names())
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
     fn emit_statement(&mut self, statement: Statement) {
         match statement {
             Statement::Function(function) => self.emit_statement_function(function),
@@ -51,7 +85,10 @@ impl CodeGenerator {
 
     fn emit_statement_function(&mut self, f: StatementFunction) {
         self.context.reset();
+        let start_line = f.identifier.span.line;
         let name = f.identifier.label;
+        // Numbered before its body, so that it can call itself
+        let index = self.program.declare_function(&name);
         let arity = f.parameters.parameters.len();
         for (index, parameter) in f.parameters.parameters.into_iter().enumerate() {
             self.context.locals.insert(parameter.identifier.label, index);
@@ -59,10 +96,15 @@ impl CodeGenerator {
         for statement in f.body.statements {
             self.emit_statement(statement);
         }
+        self.context.span = f.identifier.span;
         self.context.push(Opcode::RETURN);
         let max_locals = self.context.locals.len();
         let code = self.context.code.clone();
-        self.program.fns.insert(name, DefinedFn::new(0, arity, code, max_locals));
+        let function = DefinedFn::new(start_line, arity, code, max_locals)
+            .with_spans(self.context.spans.clone())
+            .with_local_names(self.context.local_names())
+            .with_name(&name);
+        self.program.fns[index] = function;
     }
 
     fn emit_statement_vardecl(&mut self, v: VariableDeclaration) {
@@ -70,10 +112,12 @@ impl CodeGenerator {
     }
 
     fn emit_statement_assign(&mut self, a: StatementAssignment) {
+        self.context.span = a.span;
         self.context.push(Opcode::LSTORE(self.context.locals.get(&a.var_name.unwrap()).unwrap().to_owned()));
     }
 
     fn emit_statement_expression(&mut self, expression: StatementExpression) {
+        self.context.span = expression.get_span();
         match expression {
             StatementExpression::Call(call) => self.emit_expression_call(call),
             StatementExpression::Binary(binary) => self.emit_expression_binary(binary),
@@ -87,10 +131,11 @@ impl CodeGenerator {
 
     fn emit_expression_ternary(&mut self, ternary: ExpressionTernary) {
         let before_cond_offset = self.context.code.code.len();
-        self.context.code.push(Opcode::NOP);
+        self.context.push(Opcode::NOP);
         self.emit_statement_expression(*ternary.consequence);
         let after_consq_offset = self.context.code.code.len();
-        self.context.code.push(Opcode::NOP);
+        self.context.span = ternary.span;
+        self.context.push(Opcode::NOP);
         self.emit_statement_expression(*ternary.alternative);
         let after_alt_offset = self.context.code.code.len();
         self.context.code.push_at(Opcode::JUMPNOTIF(after_consq_offset + 1), before_cond_offset);
@@ -98,14 +143,19 @@ impl CodeGenerator {
     }
 
     fn emit_expression_call(&mut self, call: ExpressionCall) {
-        self.context.push(Opcode::INVOKE(call.identifier.label));
+        let name = call.identifier.label;
+        let opcode = match self.program.function_index(&name) {
+            Some(index) => Opcode::INVOKE(index),
+            // The checker only lets through calls to natives and to functions defined before
+            None => Opcode::INVOKE_NATIVE(self.program.declare_native(&name)),
+        };
+        self.context.push(opcode);
     }
 
     fn emit_expression_binary(&mut self, binary: ExpressionBinary) {
         let operands_types = binary.operands_types.unwrap();
         match binary.operator {
             BinaryOperator::Plus => self.emit_binary_plus(operands_types),
-            BinaryOperator::Minus => self.emit_binary_minus(operands_types),
             BinaryOperator::GratherThan => self.emit_binary_gt(operands_types),
         };
     }
@@ -113,14 +163,7 @@ impl CodeGenerator {
     fn emit_binary_plus(&mut self, operands_types: Type) {
         match operands_types {
             Type::Integer => self.context.push(Opcode::IADD),
-            Type::String => todo!(),
-            _ => unreachable!(),
-        }
-    }
-
-    fn emit_binary_minus(&mut self, operands_types: Type) {
-        match operands_types {
-            Type::Integer => todo!(),
+            // The checker rejects `+` on anything else
             _ => unreachable!(),
         }
     }
@@ -156,3 +199,59 @@ impl CodeGenerator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::try_compile;
+    use bug::bytecode::Opcode;
+    use bug::stdlib::list_natives;
+    use bug::vm::{Engine, RuntimeError};
+
+    const SOURCE: &str =
+        "fn add(int lhs, int rhs) int ->\n    lhs rhs +;\n\nfn main() int ->\n    2147483647 1 @add;\n";
+
+    #[test]
+    fn instructions_keep_their_source_location() {
+        let program = try_compile("overflow.bug", SOURCE).unwrap();
+        let add = program.function("add").unwrap();
+        let lines: Vec<usize> = (0..add.code.code.len()).map(|offset| add.span_at(offset).unwrap().line).collect();
+        // Loading both parameters and adding them, then the `RETURN` at the name of the function
+        assert_eq!(lines, [2, 2, 2, 1]);
+    }
+
+    #[test]
+    fn stack_traces_point_at_the_failing_line() {
+        let program = try_compile("overflow.bug", SOURCE).unwrap();
+        let mut engine = Engine::bootstrap(program, list_natives());
+        assert_eq!(engine.run(), Err(RuntimeError::IntegerOverflow { lhs: i32::MAX, rhs: 1 }));
+        let trace = engine.stack_trace();
+        let frames: Vec<&str> = trace.lines().filter(|line| line.starts_with("    At ")).collect();
+        assert_eq!(frames, ["    At function `add` (overflow.bug:2:13)", "    At function `main` (overflow.bug:5:18)"]);
+    }
+
+    #[test]
+    fn stack_traces_only_show_excerpts_of_the_source_given() {
+        let program = try_compile("overflow.bug", SOURCE).unwrap();
+        let excerpts = |engine: Engine| {
+            let mut engine = engine;
+            assert!(engine.run().is_err());
+            engine.stack_trace().lines().filter(|line| line.starts_with("  2 | ") || line.starts_with("  5 | ")).count()
+        };
+        // The engine never opens `overflow.bug` on its own, the name comes from the binary
+        assert_eq!(excerpts(Engine::bootstrap(program.clone(), list_natives())), 0);
+        assert_eq!(excerpts(Engine::bootstrap(program.clone(), list_natives()).with_source(SOURCE.to_string())), 2);
+        // Spans past the end of a source can't come from it
+        assert_eq!(excerpts(Engine::bootstrap(program, list_natives()).with_source("fn".to_string())), 0);
+    }
+
+    #[test]
+    fn calls_are_resolved_to_functions_or_natives() {
+        let source = "fn twice(int n) int -> n n +;\nfn main() void -> 3 @twice \"a\" @len + @write;\n";
+        let program = try_compile("calls.bug", source).unwrap();
+        let main = program.function("main").unwrap();
+        let calls = main.code.code.iter().filter(|op| matches!(op, Opcode::INVOKE(_) | Opcode::INVOKE_NATIVE(_)));
+        let calls: Vec<String> = calls.map(|op| format!("{:?}", op)).collect();
+        assert_eq!(calls, ["INVOKE(0)", "INVOKE_NATIVE(0)", "INVOKE_NATIVE(1)"]);
+        assert_eq!(program.natives, ["len", "write"]);
+    }
+}
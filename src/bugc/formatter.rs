@@ -0,0 +1,468 @@
+use super::lexer::{Lexer, LexerError};
+use super::token::TokenKind;
+
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// Formats bug source code into its canonical layout, comments included
+pub fn format_source(source: &str) -> Result<String, LexerError> {
+    let (words, dangling) = split_words(source)?;
+    let mut out = Printer::new();
+    let mut cursor = 0;
+    while cursor < words.len() {
+        if cursor > 0 && words[cursor].blank_before {
+            out.blank_line();
+        }
+        let end = if words[cursor].kind == TokenKind::Function {
+            let end = item_end(&words, cursor);
+            match FunctionLayout::parse(&words[cursor..end]) {
+                Some(layout) => layout.print(&mut out),
+                None => out.fill(&words[cursor..end], ""),
+            }
+            end
+        } else {
+            let end =
+                words[cursor..].iter().position(|w| w.kind == TokenKind::Function).map_or(words.len(), |p| cursor + p);
+            out.fill(&words[cursor..end], "");
+            end
+        };
+        cursor = end;
+    }
+    if !dangling.comments.is_empty() {
+        if !words.is_empty() && dangling.blank_before {
+            out.blank_line();
+        }
+        for comment in &dangling.comments {
+            out.line(comment);
+        }
+    }
+    Ok(out.finish())
+}
+
+/// The kinds of the significant tokens of `source`, which must be equal before and after formatting
+pub fn significant_tokens(source: &str) -> Result<Vec<TokenKind>, LexerError> {
+    let mut lexer = Lexer::new(source);
+    let mut kinds: Vec<TokenKind> = vec![];
+    loop {
+        let token = lexer.next_token()?;
+        if token.kind == TokenKind::Eof {
+            break;
+        }
+        // A trailing comma in a parameter list is optional, the formatter adds or drops it
+        if token.kind == TokenKind::RightParent && kinds.last() == Some(&TokenKind::Comma) {
+            kinds.pop();
+        }
+        kinds.push(token.kind);
+    }
+    Ok(kinds)
+}
+
+/// A piece of code printed as a unit together with the comments that belong to it
+#[derive(Debug, Clone)]
+struct Word {
+    text: String,
+    kind: TokenKind,
+    /// Comments on their own lines right before the word, an empty one stands for a blank line between them
+    leading: Vec<String>,
+    /// A comment after the word on the same line
+    trailing: Option<String>,
+    /// Whether an empty line separates the word (or its leading comments) from what comes before
+    blank_before: bool,
+}
+
+impl Word {
+    fn has_comments(&self) -> bool {
+        !self.leading.is_empty() || self.trailing.is_some()
+    }
+
+    /// Appends `other` to this word, moving its comments along
+    fn glue(mut self, separator: &str, other: Word) -> Word {
+        self.text.push_str(separator);
+        self.text.push_str(&other.text);
+        self.leading.extend(other.leading);
+        self.trailing = match (self.trailing, other.trailing) {
+            (Some(lhs), Some(rhs)) => Some(format!("{} {}", lhs, rhs)),
+            (lhs, rhs) => lhs.or(rhs),
+        };
+        self
+    }
+}
+
+struct Dangling {
+    comments: Vec<String>,
+    blank_before: bool,
+}
+
+fn split_words(source: &str) -> Result<(Vec<Word>, Dangling), LexerError> {
+    let mut lexer = Lexer::lossless(source);
+    let mut words: Vec<Word> = vec![];
+    let mut leading: Vec<String> = vec![];
+    let mut newline_before = false;
+    let mut blank_before = false;
+    loop {
+        let token = lexer.next_token()?;
+        match token.kind {
+            TokenKind::Eof => break,
+            TokenKind::Whitespace(whitespace) => {
+                let newlines = whitespace.matches('\n').count();
+                newline_before |= newlines > 0;
+                if newlines > 1 && leading.is_empty() {
+                    blank_before = true;
+                } else if newlines > 1 && leading.last().is_some_and(|comment| !comment.is_empty()) {
+                    leading.push(String::new());
+                }
+            }
+            TokenKind::Comment(comment) => {
+                let comment = comment.trim_end().to_string();
+                match words.last_mut() {
+                    Some(word) if !newline_before && leading.is_empty() && word.trailing.is_none() => {
+                        word.trailing = Some(comment)
+                    }
+                    _ => leading.push(comment),
+                }
+                newline_before = false;
+            }
+            kind => {
+                let text = source[token.span.start..=token.span.end].to_string();
+                let leading = std::mem::take(&mut leading);
+                words.push(Word { text, kind, leading, trailing: None, blank_before });
+                newline_before = false;
+                blank_before = false;
+            }
+        }
+    }
+    // Blank lines at the end of the file aren't kept
+    while leading.last().is_some_and(|comment| comment.is_empty()) {
+        leading.pop();
+    }
+    Ok((glue_calls(glue_list_types(words)), Dangling { comments: leading, blank_before }))
+}
+
+/// Joins every `@` with the name that follows it
+fn glue_calls(words: Vec<Word>) -> Vec<Word> {
+    let mut glued: Vec<Word> = vec![];
+    let mut words = words.into_iter().peekable();
+    while let Some(word) = words.next() {
+        if word.kind == TokenKind::At
+            && matches!(words.peek(), Some(next) if matches!(next.kind, TokenKind::Identifier(_)))
+        {
+            let name = words.next().unwrap();
+            glued.push(word.glue("", name));
+        } else {
+            glued.push(word);
+        }
+    }
+    glued
+}
+
+/// Joins the brackets of a list type like `[str]` with the type inside
+fn glue_list_types(words: Vec<Word>) -> Vec<Word> {
+    let mut glued: Vec<Word> = vec![];
+    for word in words {
+        glued.push(word);
+        // Nested lists get glued from the inside out
+        let count = glued.len();
+        if count >= 3
+            && glued[count - 1].kind == TokenKind::RightBracket
+            && glued[count - 3].kind == TokenKind::LeftBracket
+        {
+            let close = glued.pop().unwrap();
+            let element = glued.pop().unwrap();
+            let open = glued.pop().unwrap();
+            glued.push(open.glue("", element).glue("", close));
+        }
+    }
+    glued
+}
+
+/// Index right after the `;` closing the function starting at `start`
+fn item_end(words: &[Word], start: usize) -> usize {
+    words[start..].iter().position(|w| w.kind == TokenKind::Semicolon).map_or(words.len(), |p| start + p + 1)
+}
+
+/// Joins `? consequence : alternative` into single words so that they never get split across lines
+fn group_ternaries(words: Vec<Word>) -> Vec<Word> {
+    let mut grouped: Vec<Word> = vec![];
+    let mut words = words.into_iter().peekable();
+    while let Some(word) = words.next() {
+        grouped.push(take_ternary(word, &mut words));
+    }
+    grouped
+}
+
+fn take_ternary(word: Word, words: &mut std::iter::Peekable<std::vec::IntoIter<Word>>) -> Word {
+    if word.kind != TokenKind::QuestionMark {
+        return word;
+    }
+    let Some(consequence) = take_arm(words) else { return word };
+    let word = word.glue(" ", consequence);
+    if !matches!(words.peek(), Some(next) if next.kind == TokenKind::Colon) {
+        return word;
+    }
+    let word = word.glue(" ", words.next().unwrap());
+    match take_arm(words) {
+        Some(alternative) => word.glue(" ", alternative),
+        None => word,
+    }
+}
+
+fn take_arm(words: &mut std::iter::Peekable<std::vec::IntoIter<Word>>) -> Option<Word> {
+    match words.peek()?.kind {
+        TokenKind::Semicolon | TokenKind::Colon => None,
+        _ => {
+            let arm = words.next().unwrap();
+            Some(take_ternary(arm, words))
+        }
+    }
+}
+
+/// A function definition split into the parts that get laid out differently
+struct FunctionLayout {
+    /// `fn name(`
+    open: Word,
+    /// `type name` of every parameter
+    parameters: Vec<Word>,
+    /// `) type ->`
+    close: Word,
+    /// The body, with the closing `;` glued to the last word
+    body: Vec<Word>,
+}
+
+impl FunctionLayout {
+    fn parse(words: &[Word]) -> Option<Self> {
+        let mut words = words.iter().cloned();
+        let function = words.next()?;
+        let name = words.next()?;
+        let left_parent = words.next().filter(|w| w.kind == TokenKind::LeftParent)?;
+        let open = function.glue(" ", name).glue("", left_parent);
+
+        let mut parameters: Vec<Word> = vec![];
+        let mut close = loop {
+            let word = words.next()?;
+            match word.kind {
+                TokenKind::RightParent => break word,
+                TokenKind::Comma => {
+                    // The separators are printed by the layout, their comments stay with the parameter
+                    let parameter = parameters.pop()?;
+                    parameters.push(parameter.glue("", Word { text: String::new(), ..word }));
+                }
+                _ => {
+                    let name = words.next()?;
+                    parameters.push(word.glue(" ", name));
+                }
+            }
+        };
+        loop {
+            let word = words.next()?;
+            let arrow = word.kind == TokenKind::Arrow;
+            close = close.glue(" ", word);
+            if arrow {
+                break;
+            }
+        }
+
+        let mut body = group_ternaries(words.collect());
+        let semicolon = body.pop().filter(|w| w.kind == TokenKind::Semicolon)?;
+        match body.pop() {
+            Some(last) if semicolon.leading.is_empty() => body.push(last.glue("", semicolon)),
+            Some(last) => {
+                body.push(last);
+                body.push(semicolon);
+            }
+            None => close = close.glue(" ", semicolon),
+        }
+        Some(Self { open, parameters, close, body })
+    }
+
+    fn print(&self, out: &mut Printer) {
+        if let Some(line) = self.flat() {
+            for comment in &self.open.leading {
+                out.line(comment);
+            }
+            out.line(&line);
+            return;
+        }
+
+        let header = self.flat_header();
+        let break_parameters = self.open.trailing.is_some()
+            || self.parameters.iter().any(Word::has_comments)
+            || !self.close.leading.is_empty()
+            || header.len() > MAX_WIDTH;
+        if break_parameters {
+            out.word(&self.open, "");
+            out.flush();
+            for parameter in &self.parameters {
+                out.word(&Word { text: format!("{},", parameter.text), ..parameter.clone() }, INDENT);
+                out.flush();
+            }
+            out.word(&self.close, "");
+            out.flush();
+        } else {
+            for comment in &self.open.leading {
+                out.line(comment);
+            }
+            out.line(&with_comment(header, &self.close.trailing));
+        }
+        out.fill(&self.body, INDENT);
+    }
+
+    /// The whole function on one line, if it fits and has no comments in the middle
+    fn flat(&self) -> Option<String> {
+        let last = self.body.last().unwrap_or(&self.close);
+        let mut words = vec![&self.open, &self.close];
+        words.extend(self.parameters.iter());
+        words.extend(self.body.iter());
+        for word in words {
+            let leading_allowed = std::ptr::eq(word, &self.open);
+            let trailing_allowed = std::ptr::eq(word, last);
+            if (!word.leading.is_empty() && !leading_allowed) || (word.trailing.is_some() && !trailing_allowed) {
+                return None;
+            }
+        }
+        let mut line = self.flat_header();
+        for word in &self.body {
+            line.push(' ');
+            line.push_str(&word.text);
+        }
+        if line.len() > MAX_WIDTH {
+            return None;
+        }
+        Some(with_comment(line, &last.trailing))
+    }
+
+    fn flat_header(&self) -> String {
+        let parameters: Vec<&str> = self.parameters.iter().map(|p| p.text.as_str()).collect();
+        format!("{}{}{}", self.open.text, parameters.join(", "), self.close.text)
+    }
+}
+
+fn with_comment(mut line: String, comment: &Option<String>) -> String {
+    if let Some(comment) = comment {
+        line.push(' ');
+        line.push_str(comment);
+    }
+    line
+}
+
+struct Printer {
+    lines: Vec<String>,
+    current: String,
+}
+
+impl Printer {
+    fn new() -> Self {
+        Self { lines: vec![], current: String::new() }
+    }
+
+    fn line(&mut self, line: &str) {
+        self.flush();
+        self.lines.push(line.trim_end().to_string());
+    }
+
+    fn blank_line(&mut self) {
+        self.flush();
+        self.lines.push(String::new());
+    }
+
+    /// Prints `words` separated by spaces, wrapping lines at `MAX_WIDTH`
+    fn fill(&mut self, words: &[Word], indent: &str) {
+        for word in words {
+            self.word(word, indent);
+        }
+        self.flush();
+    }
+
+    /// Appends `word` to the current line, or starts a new one when it doesn't fit
+    fn word(&mut self, word: &Word, indent: &str) {
+        if !word.leading.is_empty() {
+            self.flush();
+            for comment in &word.leading {
+                self.lines.push(format!("{}{}", indent, comment).trim_end().to_string());
+            }
+        }
+        if self.current.is_empty() {
+            self.current = format!("{}{}", indent, word.text);
+        } else if self.current.len() + 1 + word.text.len() > MAX_WIDTH {
+            self.flush();
+            self.current = format!("{}{}", indent, word.text);
+        } else {
+            self.current.push(' ');
+            self.current.push_str(&word.text);
+        }
+        if let Some(comment) = &word.trailing {
+            self.current.push(' ');
+            self.current.push_str(comment);
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.current.is_empty() {
+            let line = std::mem::take(&mut self.current);
+            self.lines.push(line.trim_end().to_string());
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        let mut out = self.lines.join("\n");
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_source, significant_tokens};
+
+    fn format(source: &str) -> String {
+        format_source(source).unwrap()
+    }
+
+    fn assert_idempotent(source: &str) {
+        let once = format(source);
+        assert_eq!(format(&once), once);
+        assert_eq!(significant_tokens(&once).unwrap(), significant_tokens(source).unwrap());
+    }
+
+    #[test]
+    fn keeps_the_blank_line_after_a_header_comment() {
+        let source = "// header comment\n\nfn main() void -> \"hi\" @write;\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn keeps_blank_lines_between_comments() {
+        let source = "// a\n\n// b\nfn f() int -> 1; // trailing\n\n\n// c\n\nfn main() void ->\n    // inside\n\n    1 @f @write;\n// end\n\n";
+        let expected = "// a\n\n// b\nfn f() int -> 1; // trailing\n\n// c\n\nfn main() void ->\n    // inside\n\n    1 @f @write;\n// end\n";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn comments_survive_formatting() {
+        let source = "// top\nfn   sum( int x, // first\n int y ) int -> // body\n x y + ; // done\n// bottom\n";
+        let formatted = format(source);
+        for comment in ["// top", "// first", "// body", "// done", "// bottom"] {
+            assert!(formatted.contains(comment), "lost `{}` in:\n{}", comment, formatted);
+        }
+        assert_idempotent(source);
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let sources = [
+            include_str!("../../examples/hello.bug"),
+            include_str!("../../examples/max.bug"),
+            include_str!("../../examples/sum.bug"),
+            "fn main() void -> 1 2 > ? \"yes\" : \"no\" @write;",
+            "fn f([[str]] xs) int -> 1;\nfn main([str] args) int -> 0;",
+            "fn main() void -> \"a\" @write \"b\" @write \"c\" @write \"d\" @write \"e\" @write \"f\" @write \"g\" @write \"h\" @write;",
+            "fn main() void ->\n\n// dangling\n",
+        ];
+        for source in sources {
+            assert_idempotent(source);
+        }
+    }
+}
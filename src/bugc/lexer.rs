@@ -1,6 +1,7 @@
-use super::span::Span;
 use super::token::{Token, TokenKind};
+use bug::span::Span;
 
+#[derive(Debug)]
 pub struct LexerError {
     pub message: String,
     pub location: Span,
@@ -18,26 +19,44 @@ pub struct Lexer<'a> {
     input: &'a str,
     cursor: usize,
     span: Span,
+    keep_trivia: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Lexer { input, cursor: 0, line: 1, colm: 1, span: Span::default() }
+        Lexer { input, cursor: 0, line: 1, colm: 1, span: Span::default(), keep_trivia: false }
+    }
+
+    /// A lexer that also yields whitespace and comments, so the input can be rebuilt from its tokens
+    pub fn lossless(input: &'a str) -> Self {
+        Lexer { keep_trivia: true, ..Lexer::new(input) }
     }
 
     pub fn next_token(&mut self) -> Result<Token, LexerError> {
-        self.skip_whitespace();
+        if !self.keep_trivia {
+            self.skip_trivia();
+        }
         self.update_span();
         if self.is_eof() {
             return Ok(Token::new(TokenKind::Eof, self.get_span()));
         }
+        if self.starts_with("//") {
+            let comment = self.chop_while(|x| x != '\n');
+            return Ok(Token::new(TokenKind::Comment(comment), self.get_span()));
+        }
         match self.peek_one() {
+            chr if chr.is_whitespace() => {
+                let whitespace = self.chop_while(|x| x.is_whitespace());
+                Ok(Token::new(TokenKind::Whitespace(whitespace), self.get_span()))
+            }
             '@' => Ok(self.read_simple_token(TokenKind::At)),
             '.' => Ok(self.read_simple_token(TokenKind::Dot)),
             '+' => Ok(self.read_simple_token(TokenKind::Plus)),
             ';' => Ok(self.read_simple_token(TokenKind::Semicolon)),
             '(' => Ok(self.read_simple_token(TokenKind::LeftParent)),
             ')' => Ok(self.read_simple_token(TokenKind::RightParent)),
+            '[' => Ok(self.read_simple_token(TokenKind::LeftBracket)),
+            ']' => Ok(self.read_simple_token(TokenKind::RightBracket)),
             ',' => Ok(self.read_simple_token(TokenKind::Comma)),
             '-' => Ok(self.read_check_ahead("->", TokenKind::Minus, TokenKind::Arrow)),
             '>' => Ok(self.read_simple_token(TokenKind::RightAngle)),
@@ -50,7 +69,7 @@ impl<'a> Lexer<'a> {
             _ => {
                 let chr = self.peek_one();
                 self.advance_one();
-                return Err(LexerError::new(format!("Unexpected token `{}`", chr), self.get_span()));
+                Err(LexerError::new(format!("Unexpected token `{}`", chr), self.get_span()))
             }
         }
     }
@@ -72,7 +91,7 @@ impl<'a> Lexer<'a> {
                 break;
             }
             if self.is_eof() || self.peek_one() == '\n' {
-                return Err(LexerError::new(format!("Unterminated string literal"), self.get_span()));
+                return Err(LexerError::new("Unterminated string literal".to_string(), self.get_span()));
             }
             self.advance_one();
         }
@@ -89,7 +108,7 @@ impl<'a> Lexer<'a> {
             } else {
                 self.colm += 1;
             }
-            self.cursor += 1;
+            self.cursor += c.len_utf8();
         }
     }
 
@@ -103,7 +122,7 @@ impl<'a> Lexer<'a> {
             self.advance_may(expected.len());
             return Token::new(complex, self.get_span());
         }
-        Token::new(simple, self.get_span())
+        self.read_simple_token(simple)
     }
 
     fn advance_may(&mut self, count: usize) {
@@ -118,8 +137,7 @@ impl<'a> Lexer<'a> {
 
     fn read_simple_token(&mut self, kind: TokenKind) -> Token {
         self.advance_one();
-        let token = Token::new(kind, self.get_span());
-        return token;
+        Token::new(kind, self.get_span())
     }
 
     fn update_span(&mut self) {
@@ -129,12 +147,18 @@ impl<'a> Lexer<'a> {
     }
 
     fn get_span(&mut self) -> Span {
-        self.span.end = self.cursor - 1;
-        return self.span.clone();
+        self.span.end = self.cursor.saturating_sub(1);
+        self.span.clone()
     }
 
-    fn skip_whitespace(&mut self) {
-        self.chop_while(|x| x.is_whitespace());
+    fn skip_trivia(&mut self) {
+        loop {
+            self.chop_while(|x| x.is_whitespace());
+            if !self.starts_with("//") {
+                break;
+            }
+            self.chop_while(|x| x != '\n');
+        }
     }
 
     fn is_eof(&self) -> bool {
@@ -153,6 +177,6 @@ impl<'a> Lexer<'a> {
         while !self.is_eof() && predicate(self.peek_one()) {
             self.advance_one();
         }
-        return self.input[start..self.cursor].to_string();
+        self.input[start..self.cursor].to_string()
     }
 }
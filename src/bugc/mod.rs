@@ -1,16 +1,16 @@
-#![allow(unused)]
+#![allow(unused, clippy::unit_arg)]
 
-mod ast;
-mod checker;
-mod codegenerator;
-mod highlighter;
-mod lexer;
-mod parser;
-mod span;
-mod token;
+pub mod ast;
+pub mod checker;
+pub mod codegenerator;
+pub mod formatter;
+pub mod lexer;
+pub mod parser;
+pub mod token;
 pub mod utils;
 
-use bug::{stdlib::list_natives, Program};
+use bug::stdlib::{list_natives, prototypes};
+use bug::Program;
 use checker::Checker;
 use codegenerator::CodeGenerator;
 use lexer::Lexer;
@@ -19,29 +19,71 @@ use std::{env, io::Write};
 use utils::{get_file_stem, read_file};
 
 pub fn compile(file_path: &str) -> Program {
-    let file_content = match read_file(&file_path) {
+    let file_content = match read_file(file_path) {
         Ok(contents) => contents,
         Err(err) => {
-            eprintln!("[Error]: Couldn't read file {} {}", file_path, err.to_string());
+            eprintln!("[Error]: Couldn't read file {} {}", file_path, err);
+            std::process::exit(1);
+        }
+    };
+    try_compile(file_path, &file_content).unwrap_or_else(|errors| {
+        eprint!("{}", errors);
+        std::process::exit(1);
+    })
+}
+
+/// Compiles `source`, returning the rendered errors instead of exiting
+pub fn try_compile(file_path: &str, source: &str) -> Result<Program, String> {
+    let mut lexer = Lexer::new(source);
+    let mut ast = Parser::new(file_path, source, &mut lexer).parse().map_err(|err| err.to_string())?;
+    let mut checker = Checker::new(file_path, source, prototypes(&list_natives()));
+    if let Some(diagnostics) = checker.check(&mut ast) {
+        return Err(diagnostics.to_string());
+    }
+    let mut generator = CodeGenerator::setup(file_path);
+    Ok(generator.emit(ast))
+}
+
+/// Formats the file in place, or with `check` only reports whether it is formatted. Returns `false`
+/// when a checked file isn't formatted
+pub fn format(file_path: &str, check: bool) -> bool {
+    let file_content = match read_file(file_path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("[Error]: Couldn't read file {} {}", file_path, err);
             std::process::exit(1);
         }
     };
     let mut lexer = Lexer::new(&file_content);
-    let mut ast = match Parser::new(&file_path, &file_content, &mut lexer).parse() {
-        Ok(ast) => ast,
+    if let Err(err) = Parser::new(file_path, &file_content, &mut lexer).parse() {
+        eprint!("{}", err);
+        std::process::exit(1);
+    }
+    let formatted = match formatter::format_source(&file_content) {
+        Ok(formatted) => formatted,
         Err(err) => {
-            eprint!("{}", err);
+            eprintln!("[Error]: Couldn't format file {}: {}", file_path, err.message);
             std::process::exit(1);
         }
     };
-    let mut checker = Checker::new(&file_path, &file_content, list_natives());
-    if let Some(diagnostics) = checker.check(&mut ast) {
-        eprint!("{}", diagnostics);
+    let same_tokens =
+        formatter::significant_tokens(&file_content).ok() == formatter::significant_tokens(&formatted).ok();
+    if !same_tokens {
+        eprintln!("[Error]: Formatting {} would change the program, the file was left untouched", file_path);
         std::process::exit(1);
     }
-    let mut generator = CodeGenerator::setup();
-    let program = generator.emit(ast);
-    return program;
+    if formatted == file_content {
+        return true;
+    }
+    if check {
+        println!("{} is not formatted", file_path);
+        return false;
+    }
+    std::fs::write(file_path, formatted).unwrap_or_else(|err| {
+        eprintln!("[Error]: Couldn't write formatted file {}: {}", file_path, err);
+        std::process::exit(1);
+    });
+    true
 }
 
 fn main() {
@@ -52,12 +94,12 @@ fn main() {
     }
     let file_path = &command_line_args[1];
     let program = compile(file_path);
-    let program_binary = bincode::serialize(&program).unwrap_or_else(|err| {
-        eprintln!("[ERROR]: Couldn't serialize your program: {}", err.to_string());
+    let program_binary = bug::binary::encode(&program).unwrap_or_else(|err| {
+        eprintln!("[ERROR]: Couldn't serialize your program: {}", err);
         std::process::exit(1);
     });
-    let mut out_file = std::fs::File::create(&format!("{}.bin", get_file_stem(file_path))).unwrap();
-    out_file.write(&program_binary).unwrap_or_else(|err| {
+    let mut out_file = std::fs::File::create(format!("{}.bin", get_file_stem(file_path))).unwrap();
+    out_file.write_all(&program_binary).unwrap_or_else(|err| {
         eprintln!("[ERROR] Couldn't save serialized program into file: {}", err);
         std::process::exit(1);
     });
@@ -1,8 +1,8 @@
 use super::ast::*;
-use super::highlighter::highlight_error;
 use super::lexer::Lexer;
-use super::span::Span;
 use super::token::{Token, TokenKind};
+use bug::highlighter::highlight_error;
+use bug::span::Span;
 use bug::Type;
 
 pub struct Parser<'a> {
@@ -47,7 +47,7 @@ impl<'a> Parser<'a> {
     fn parse_statement(&mut self) -> Result<Statement, ParserError> {
         match self.current_token.kind {
             TokenKind::Function => Ok(Statement::Function(self.parse_statement_function()?)),
-            TokenKind::TypeInt | TokenKind::TypeStr | TokenKind::TypeVoid => {
+            TokenKind::TypeInt | TokenKind::TypeStr | TokenKind::TypeVoid | TokenKind::LeftBracket => {
                 Ok(Statement::Variable(self.parse_statement_vardecl()?))
             }
             TokenKind::Equal => Ok(Statement::Assignment(self.parse_statement_assign())),
@@ -119,6 +119,16 @@ impl<'a> Parser<'a> {
             TokenKind::TypeInt => Type::Integer,
             TokenKind::TypeVoid => Type::Void,
             TokenKind::TypeStr => Type::String,
+            TokenKind::LeftBracket => {
+                self.bump()?;
+                let element_type = self.parse_type_annotation()?;
+                if self.current_token.kind != TokenKind::RightBracket {
+                    return Err(
+                        self.error("Expecting `]` after the type of the list's elements", &self.current_token.span)
+                    );
+                }
+                Type::List(Box::new(element_type))
+            }
             _ => return Err(self.error_expect_type_annotation(&self.current_token.span)),
         };
         self.bump()?;
@@ -170,7 +180,6 @@ impl<'a> Parser<'a> {
     fn parse_expression_binary(&mut self) -> Result<ExpressionBinary, ParserError> {
         let op = match self.current_token.kind {
             TokenKind::Plus => BinaryOperator::Plus,
-            TokenKind::Minus => BinaryOperator::Minus,
             TokenKind::RightAngle => BinaryOperator::GratherThan,
             _ => unreachable!("Invalid binary operator {:#?}", self.current_token.kind),
         };
@@ -210,6 +219,8 @@ impl<'a> Parser<'a> {
 
 pub struct ParserError {
     message: String,
+    pub reason: String,
+    pub span: Span,
 }
 
 impl core::fmt::Display for ParserError {
@@ -237,12 +248,12 @@ impl<'a> Parser<'a> {
 
     fn error(&self, message: &str, span: &Span) -> ParserError {
         let mut error = String::new();
-        error.push_str(&self.error_header(&span));
+        error.push_str(&self.error_header(span));
         error.push_str(message);
         error.push_str("\n\n");
-        error.push_str(&&highlight_error(&self.raw, span.start, span.end));
+        error.push_str(&highlight_error(self.raw, span.start, span.end));
         error.push('\n');
-        ParserError { message: error }
+        ParserError { message: error, reason: message.to_string(), span: span.clone() }
     }
 
     fn error_header(&self, span: &Span) -> String {
@@ -1,9 +1,13 @@
-use super::span::Span;
+use bug::span::Span;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     Eof,
 
+    // Trivia, only produced by a lossless lexer
+    Whitespace(String),
+    Comment(String),
+
     Integer(i32),
     String(String),
 
@@ -18,6 +22,8 @@ pub enum TokenKind {
     Semicolon,
     LeftParent,
     RightParent,
+    LeftBracket,
+    RightBracket,
     Colon,
 
     TypeInt,
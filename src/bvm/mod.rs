@@ -1,32 +1,67 @@
 #![allow(unused)]
 
-pub mod engine;
-mod frame;
-mod stack;
-
 use bug::stdlib::list_natives;
 use bug::utils::read_file_bytes;
-use bug::Program;
-use engine::Engine;
+use bug::vm::{verifier, Engine, RuntimeError};
+use bug::{Object, Program};
 use std::env;
 
+/// Reads a compiled program and verifies it can be run safely
 pub fn load_program_binary(file_path: &str) -> Program {
-    let contents = match read_file_bytes(&file_path) {
+    let program = read_program_binary(file_path);
+    if let Err(err) = verifier::verify(&program, &list_natives()) {
+        eprintln!("[Error]: {} is not a valid program: {}", file_path, err);
+        std::process::exit(1);
+    }
+    program
+}
+
+/// Reads a compiled program as is, without verifying it
+pub fn read_program_binary(file_path: &str) -> Program {
+    let contents = match read_file_bytes(file_path) {
         Ok(xs) => xs,
         Err(err) => {
-            eprintln!("{:#?}", err);
+            eprintln!("[Error]: Couldn't read file {} {}", file_path, err);
             std::process::exit(1);
         }
     };
-    match bincode::deserialize(&contents) {
+    match bug::binary::decode(&contents) {
         Ok(program) => program,
         Err(err) => {
-            eprintln!("[Error]: Couldn't deserialize your program: {:#?}\n", err);
+            eprintln!("[Error]: Couldn't load {}: {}", file_path, err);
             std::process::exit(1);
         }
     }
 }
 
+/// Reports a runtime error with the stack trace of where it happened and exits with its exit code
+pub fn exit_on_runtime_error(engine: &Engine, err: RuntimeError) -> ! {
+    eprintln!("RUNTIME EXCEPTION: {}", err);
+    eprint!("{}", engine.stack_trace());
+    std::process::exit(err.exit_code());
+}
+
+/// `main` may return an `int`, it becomes the exit status of the process
+pub fn exit_with_result(result: Option<&Object>) {
+    match exit_code(result) {
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            eprintln!("RUNTIME EXCEPTION: {}", err);
+            std::process::exit(err.exit_code());
+        }
+    }
+}
+
+/// The exit code for what `main` returned, 0 unless it's an `int`. Codes of the limits can be returned too
+pub fn exit_code(result: Option<&Object>) -> Result<i32, RuntimeError> {
+    match result {
+        Some(Object::Integer(code)) if (0..=255).contains(code) => Ok(*code),
+        Some(Object::Integer(code)) => Err(RuntimeError::ExitCodeOutOfRange(*code)),
+        _ => Ok(0),
+    }
+}
+
 fn main() {
     let cli_args: Vec<String> = env::args().collect();
     if cli_args.len() <= 1 {
@@ -34,5 +69,40 @@ fn main() {
         std::process::exit(1);
     }
     let program = load_program_binary(&cli_args[1].clone());
-    Engine::bootstrap(program, list_natives()).run();
+    let mut engine = Engine::bootstrap(program, list_natives()).with_args(cli_args[2..].to_vec());
+    match engine.run() {
+        Ok(result) => exit_with_result(result.as_ref()),
+        Err(err) => exit_on_runtime_error(&engine, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::exit_code;
+    use bug::vm::error::EXIT_STEP_LIMIT;
+    use bug::vm::RuntimeError;
+    use bug::Object;
+
+    #[test]
+    fn main_returns_the_exit_code() {
+        assert_eq!(exit_code(Some(&Object::Integer(42))), Ok(42));
+        assert_eq!(exit_code(Some(&Object::Integer(255))), Ok(255));
+        assert_eq!(exit_code(None), Ok(0));
+    }
+
+    #[test]
+    fn exit_codes_out_of_range_are_runtime_errors() {
+        // The OS would only keep the lowest byte, 300 would exit as 44
+        let err = exit_code(Some(&Object::Integer(300))).unwrap_err();
+        assert_eq!(
+            (err.to_string().as_str(), err.exit_code()),
+            ("`main` returned 300, exit codes go from 0 to 255", 1)
+        );
+        assert_eq!(exit_code(Some(&Object::Integer(-1))), Err(RuntimeError::ExitCodeOutOfRange(-1)));
+    }
+
+    #[test]
+    fn main_can_return_the_code_of_a_limit() {
+        assert_eq!(exit_code(Some(&Object::Integer(3))), Ok(EXIT_STEP_LIMIT));
+    }
 }
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Opcode {
     /// Will do nothing for a cycle
     NOP,
@@ -11,8 +12,10 @@ pub enum Opcode {
     ICMPGT,
     /// Will return from current function
     RETURN,
-    /// Returns the value on the top of the current stack
-    INVOKE(String),
+    /// Calls the function at this index of the program's functions
+    INVOKE(usize),
+    /// Calls the native at this index of the program's natives
+    INVOKE_NATIVE(usize),
     // Will load some Object from pool and push on the stack
     LDC(usize),
     /// Will load a value from locals at provided index to the stack
@@ -25,6 +28,33 @@ pub enum Opcode {
     JUMPNOTIF(usize),
 }
 
+impl Opcode {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::NOP => "NOP",
+            Self::IADD => "IADD",
+            Self::ICMPGT => "ICMPGT",
+            Self::RETURN => "RETURN",
+            Self::INVOKE(_) => "INVOKE",
+            Self::INVOKE_NATIVE(_) => "INVOKE_NATIVE",
+            Self::LDC(_) => "LDC",
+            Self::LLOAD(_) => "LLOAD",
+            Self::LSTORE(_) => "LSTORE",
+            Self::IPUSH(_) => "IPUSH",
+            Self::JUMP(_) => "JUMP",
+            Self::JUMPNOTIF(_) => "JUMPNOTIF",
+        }
+    }
+
+    /// The offset this opcode may jump to, if it is a jump
+    pub fn jump_target(&self) -> Option<usize> {
+        match self {
+            Self::JUMP(offset) | Self::JUMPNOTIF(offset) => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ByteCodeStream {
     pub code: Vec<Opcode>,
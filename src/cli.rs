@@ -1,28 +1,147 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, Command};
 
 pub fn command_line() -> clap::ArgMatches {
-    let matches = Command::new("Bug")
+    Command::new("Bug")
         .about(env!("CARGO_PKG_DESCRIPTION"))
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .subcommand_required(true)
         .arg_required_else_help(true)
         .subcommand(
-            Command::new("run")
+            stdlib_args(limit_args(Command::new("run")))
                 .about("executes the provided program")
-                .arg(Arg::new("file").help("the bug program to run").required(true)),
+                .arg(Arg::new("file").help("the bug program to run").required(true))
+                .arg(program_args())
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .help("report where the program spends its time and write a folded-stack file")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("trace")
+                        .long("trace")
+                        .help("print every executed instruction and the operand stack after it on stderr")
+                        .conflicts_with("profile")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("trace-function")
+                        .long("trace-function")
+                        .value_name("NAME")
+                        .help("only trace the instructions of this function, can be repeated")
+                        .requires("trace")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("trace-limit")
+                        .long("trace-limit")
+                        .value_name("COUNT")
+                        .help("stop tracing after this many instructions")
+                        .requires("trace")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("trace-json")
+                        .long("trace-json")
+                        .help("trace as JSON lines")
+                        .requires("trace")
+                        .action(ArgAction::SetTrue),
+                ),
         )
         .subcommand(
-            Command::new("run-bin")
+            stdlib_args(limit_args(Command::new("run-bin")))
                 .about("executes the provided binary program")
-                .arg(Arg::new("file").help("the bug binary program to run").required(true)),
+                .arg(Arg::new("file").help("the bug binary program to run").required(true))
+                .arg(
+                    Arg::new("source")
+                        .long("source")
+                        .value_name("FILE")
+                        .help("the bug program the binary was compiled from, to show excerpts in stack traces"),
+                )
+                .arg(program_args())
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .help("report where the program spends its time and write a folded-stack file")
+                        .action(ArgAction::SetTrue),
+                ),
         )
         .subcommand(
             Command::new("compile")
                 .about("compiles the program to bug bytecode")
                 .arg(Arg::new("file").help("the bug program to compile").required(true)),
         )
-        .get_matches();
+        .subcommand(
+            Command::new("asm")
+                .about("assembles a textual bytecode program to a bug binary")
+                .arg(Arg::new("file").help("the bug assembly program").required(true)),
+        )
+        .subcommand(
+            Command::new("disasm")
+                .about("lists the bytecode of a compiled program")
+                .arg(Arg::new("file").help("the bug binary program to disassemble").required(true))
+                .arg(Arg::new("json").long("json").help("print the listing as JSON").action(ArgAction::SetTrue)),
+        )
+        .subcommand(
+            Command::new("fmt")
+                .about("formats bug programs in place")
+                .arg(Arg::new("files").help("the bug programs to format").required(true).num_args(1..))
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .help("only report unformatted files, failing if there are any")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("debug")
+                .about("runs the provided program in an interactive debugger")
+                .arg(Arg::new("file").help("the bug program to debug").required(true)),
+        )
+        .subcommand(Command::new("repl").about("starts an interactive session"))
+        .subcommand(Command::new("dap").about("starts the debug adapter over stdio"))
+        .subcommand(Command::new("lsp").about("starts the language server over stdio"))
+        .get_matches()
+}
+
+/// Options bounding what the program may use, for running untrusted code
+fn limit_args(command: Command) -> Command {
+    let limit = |name: &'static str, help: &'static str| {
+        Arg::new(name).long(name).value_name("COUNT").help(help).value_parser(clap::value_parser!(usize))
+    };
+    command
+        .arg(limit("max-steps", "abort after executing this many instructions"))
+        .arg(limit("max-call-depth", "abort when calls nest deeper than this"))
+        .arg(limit("max-stack", "abort when a frame holds more values on its stack"))
+        .arg(limit("max-heap", "abort after creating this many bytes of strings"))
+}
+
+fn stdlib_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("seed `random` so that every run gets the same numbers")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("fake-time")
+                .long("fake-time")
+                .value_name("SECONDS")
+                .help("make `time` return these seconds since the epoch and `clock` advance 1ms per call")
+                .value_parser(clap::value_parser!(i32)),
+        )
+        .arg(
+            Arg::new("no-files")
+                .long("no-files")
+                .help("leave out the natives reading and writing files, for running untrusted programs")
+                .action(ArgAction::SetTrue),
+        )
+}
 
-    return matches;
+/// Everything after `--`, passed to the program's `main`
+fn program_args() -> Arg {
+    Arg::new("args").help("arguments for the program's `main`").value_name("ARGS").num_args(0..).last(true)
 }
@@ -0,0 +1,419 @@
+use crate::bugc::try_compile;
+use crate::bugc::utils::read_file;
+use crate::bvm::exit_code;
+use crate::debugger::{Breakpoint, Resume, Session, Stop};
+use crate::lsp::transport::{read_message, write_message};
+use bug::stdlib::list_natives;
+use bug::vm::{Engine, RuntimeError, SharedBuffer};
+use bug::Object;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+
+/// The engine runs a single thread of execution
+const THREAD_ID: i64 = 1;
+
+/// Runs the debug adapter over stdin/stdout until the client disconnects
+pub fn run() {
+    let stdin = io::stdin();
+    if let Err(err) = serve(stdin.lock(), io::stdout()) {
+        eprintln!("[Error]: Debug adapter stopped: {}", err);
+        std::process::exit(1);
+    }
+}
+
+pub fn serve<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<()> {
+    let mut adapter = Adapter {
+        output,
+        seq: 0,
+        session: None,
+        program_path: String::new(),
+        stop_on_entry: false,
+        program_output: SharedBuffer::new(),
+        program_errors: SharedBuffer::new(),
+    };
+    while let Some(message) = read_message(&mut input)? {
+        if !adapter.handle(message)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct Adapter<W: Write> {
+    output: W,
+    /// Sequence number of the last message sent
+    seq: i64,
+    session: Option<Session>,
+    program_path: String,
+    stop_on_entry: bool,
+    /// What the program wrote since the last `output` event, stdout carries the protocol
+    program_output: SharedBuffer,
+    program_errors: SharedBuffer,
+}
+
+/// Ids of the `Locals` and `Stack` scopes of a frame, `variablesReference` must not be zero
+fn locals_reference(frame_id: usize) -> usize {
+    frame_id * 2 + 1
+}
+
+fn stack_reference(frame_id: usize) -> usize {
+    frame_id * 2 + 2
+}
+
+impl<W: Write> Adapter<W> {
+    /// Returns `false` once the client disconnected
+    fn handle(&mut self, message: Value) -> io::Result<bool> {
+        if message["type"] != "request" {
+            return Ok(true);
+        }
+        let command = message["command"].as_str().unwrap_or_default().to_string();
+        let arguments = &message["arguments"];
+        let result = match command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsSteppingGranularity": true,
+            })),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "continue" => self.session().map(|_| json!({ "allThreadsContinued": true })),
+            "configurationDone" | "next" | "stepIn" | "stepOut" | "pause" => self.session().map(|_| Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => self.scopes(arguments),
+            "variables" => self.variables(arguments),
+            "disconnect" | "terminate" => Ok(Value::Null),
+            _ => Err(format!("Unsupported request `{}`", command)),
+        };
+        let response = match result {
+            Ok(body) => {
+                json!({ "type": "response", "request_seq": message["seq"], "success": true, "command": command, "body": body })
+            }
+            Err(error) => {
+                json!({ "type": "response", "request_seq": message["seq"], "success": false, "command": command, "message": error })
+            }
+        };
+        self.send(response)?;
+
+        // Execution happens after the response, the client learns where it stopped through events
+        let instruction = arguments["granularity"] == "instruction";
+        match command.as_str() {
+            "initialize" => self.event("initialized", Value::Null)?,
+            "configurationDone" if self.stop_on_entry => self.stopped("entry")?,
+            "configurationDone" | "continue" => self.resume(Resume::Continue)?,
+            "next" => self.resume(if instruction { Resume::Instruction } else { Resume::StepOver })?,
+            "stepIn" => self.resume(if instruction { Resume::Instruction } else { Resume::StepInto })?,
+            "stepOut" => self.resume(Resume::StepOut)?,
+            // Execution is synchronous, by the time a pause arrives the program is already paused
+            "pause" => self.stopped("pause")?,
+            "disconnect" | "terminate" => return Ok(false),
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn session(&self) -> Result<&Session, String> {
+        self.session.as_ref().ok_or_else(|| "No program was launched".to_string())
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["program"].as_str().ok_or("Missing the `program` to debug")?;
+        let source = read_file(path).map_err(|err| format!("Couldn't read file {} {}", path, err))?;
+        let program = try_compile(path, &source)?;
+        // There's no input to give, stdin carries the protocol
+        let engine = Engine::bootstrap(program, list_natives())
+            .with_source(source)
+            .with_output(self.program_output.clone())
+            .with_error_output(self.program_errors.clone())
+            .with_input(io::empty());
+        self.session = Some(Session::new(engine).map_err(|err| err.to_string())?);
+        self.program_path = path.to_string();
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let lines: Vec<usize> = arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints.iter().filter_map(|b| b["line"].as_u64()).map(|line| line as usize).collect()
+            })
+            .unwrap_or_default();
+        let session = self.session.as_mut().ok_or("No program was launched")?;
+        session.breakpoints.retain(|breakpoint| !matches!(breakpoint, Breakpoint::Line(_)));
+        let mut verified: Vec<Value> = vec![];
+        for line in lines {
+            let exists = session.has_line(line);
+            if exists {
+                session.breakpoints.push(Breakpoint::Line(line));
+            }
+            verified.push(json!({ "verified": exists, "line": line }));
+        }
+        Ok(json!({ "breakpoints": verified }))
+    }
+
+    fn set_function_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let names: Vec<String> = arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| breakpoints.iter().filter_map(|b| b["name"].as_str()).map(String::from).collect())
+            .unwrap_or_default();
+        let session = self.session.as_mut().ok_or("No program was launched")?;
+        session.breakpoints.retain(|breakpoint| !matches!(breakpoint, Breakpoint::Function(_)));
+        let mut verified: Vec<Value> = vec![];
+        for name in names {
+            let exists = session.engine.has_function(&name);
+            if exists {
+                session.breakpoints.push(Breakpoint::Function(name));
+            }
+            verified.push(json!({ "verified": exists }));
+        }
+        Ok(json!({ "breakpoints": verified }))
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let session = self.session()?;
+        let frames: Vec<Value> = session
+            .frames()
+            .into_iter()
+            .enumerate()
+            .map(|(id, (frame, offset))| {
+                let span = frame.function.span_at(offset);
+                json!({
+                    "id": id,
+                    "name": frame.get_name(),
+                    "line": span.map_or(0, |span| span.line),
+                    "column": span.map_or(0, |span| span.column),
+                    "source": { "path": self.program_path },
+                    "instructionPointerReference": offset.to_string(),
+                })
+            })
+            .collect();
+        Ok(json!({ "totalFrames": frames.len(), "stackFrames": frames }))
+    }
+
+    fn scopes(&self, arguments: &Value) -> Result<Value, String> {
+        let frame_id = arguments["frameId"].as_u64().ok_or("Missing `frameId`")? as usize;
+        Ok(json!({ "scopes": [
+            { "name": "Locals", "variablesReference": locals_reference(frame_id), "expensive": false },
+            { "name": "Stack", "variablesReference": stack_reference(frame_id), "expensive": false },
+        ] }))
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Value, String> {
+        let reference = arguments["variablesReference"].as_u64().ok_or("Missing `variablesReference`")? as usize;
+        let session = self.session()?;
+        let frames = session.frames();
+        let frame_id = reference.checked_sub(1).map(|index| index / 2).filter(|frame_id| *frame_id < frames.len());
+        let Some(frame_id) = frame_id else {
+            return Err(format!("Unknown variables reference {}", reference));
+        };
+        let (frame, _) = frames[frame_id];
+        let engine = &session.engine;
+        let variables: Vec<Value> = if reference == locals_reference(frame_id) {
+            let locals = engine.locals(frame_id).iter().enumerate();
+            locals.map(|(index, object)| variable(&frame.function.local_name(index), object)).collect()
+        } else {
+            // The top of the stack comes first
+            let stack = engine.operands(frame_id).iter().rev().enumerate();
+            stack.map(|(depth, object)| variable(&format!("[{}]", depth), object)).collect()
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn resume(&mut self, resume: Resume) -> io::Result<()> {
+        let Some(session) = self.session.as_mut() else { return Ok(()) };
+        let stop = session.resume(resume);
+        self.flush_output()?;
+        match stop {
+            Stop::Step => self.stopped("step"),
+            Stop::Breakpoint(_) => self.stopped("breakpoint"),
+            Stop::Finished => {
+                // Like a run, the exit code is what `main` returned when it returns an `int`
+                let result = self.session.as_ref().and_then(|session| session.engine.result());
+                match exit_code(result) {
+                    Ok(code) => {
+                        self.event("exited", json!({ "exitCode": code }))?;
+                        self.event("terminated", Value::Null)
+                    }
+                    Err(err) => self.exited_on_error(err),
+                }
+            }
+            Stop::Error(err) => self.exited_on_error(err),
+        }
+    }
+
+    fn exited_on_error(&mut self, err: RuntimeError) -> io::Result<()> {
+        let output = format!("RUNTIME EXCEPTION: {}\n", err);
+        self.event("output", json!({ "category": "stderr", "output": output }))?;
+        self.event("exited", json!({ "exitCode": err.exit_code() }))?;
+        self.event("terminated", Value::Null)
+    }
+
+    fn flush_output(&mut self) -> io::Result<()> {
+        for (category, output) in [("stdout", self.program_output.take()), ("stderr", self.program_errors.take())] {
+            if !output.is_empty() {
+                self.event("output", json!({ "category": category, "output": output }))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stopped(&mut self, reason: &str) -> io::Result<()> {
+        self.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+}
+
+fn variable(name: &str, object: &Object) -> Value {
+    let value = match object {
+        Object::String(string) => format!("{:?}", string),
+        object => object.to_string(),
+    };
+    let typ = object.get_type().to_string();
+    json!({ "name": name, "value": value, "type": typ, "variablesReference": 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::serve;
+    use crate::lsp::transport::{read_message, write_message};
+    use serde_json::{json, Value};
+
+    const PROGRAM: &str = "\
+fn f(int x) int -> x 1 +;
+fn g(int y) int -> y 5 @f +;
+fn main() void -> \"hi\" @write 7 @g @write;
+";
+
+    /// Runs a scripted debugging session of `source` and returns everything the adapter sent back
+    fn session(name: &str, source: &str, requests: &[(&str, Value)]) -> Vec<Value> {
+        let path = std::env::temp_dir().join(format!("bug-dap-{}-{}.bug", std::process::id(), name));
+        std::fs::write(&path, source).unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let mut input: Vec<u8> = vec![];
+        let launch = ("launch", json!({ "program": path }));
+        for (seq, (command, arguments)) in std::iter::once(launch).chain(requests.iter().cloned()).enumerate() {
+            let request = json!({ "seq": seq + 1, "type": "request", "command": command, "arguments": arguments });
+            write_message(&mut input, &request).unwrap();
+        }
+        let mut output: Vec<u8> = vec![];
+        serve(input.as_slice(), &mut output).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut reader = output.as_slice();
+        let mut messages = vec![];
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn response<'a>(messages: &'a [Value], command: &str) -> &'a Value {
+        let response = messages.iter().find(|m| m["type"] == "response" && m["command"] == command);
+        let response = response.unwrap_or_else(|| panic!("no `{}` response", command));
+        assert_eq!(response["success"], true, "{}", response);
+        &response["body"]
+    }
+
+    /// The bodies of every `variables` response, in order
+    fn variables(messages: &[Value]) -> Vec<Value> {
+        let responses = messages.iter().filter(|m| m["type"] == "response" && m["command"] == "variables");
+        responses.map(|m| m["body"]["variables"].clone()).collect()
+    }
+
+    fn events(messages: &[Value]) -> Vec<(String, Value)> {
+        let events = messages.iter().filter(|m| m["type"] == "event");
+        events.map(|m| (m["event"].as_str().unwrap().to_string(), m["body"].clone())).collect()
+    }
+
+    #[test]
+    fn debugs_a_program_through_breakpoints() {
+        let messages = session(
+            "breakpoints",
+            PROGRAM,
+            &[
+                ("initialize", json!({ "adapterID": "bug" })),
+                ("setBreakpoints", json!({ "breakpoints": [{ "line": 1 }, { "line": 99 }] })),
+                ("setFunctionBreakpoints", json!({ "breakpoints": [{ "name": "g" }, { "name": "nope" }] })),
+                ("configurationDone", json!({})),
+                ("continue", json!({ "threadId": 1 })),
+                ("stackTrace", json!({ "threadId": 1 })),
+                ("scopes", json!({ "frameId": 1 })),
+                ("variables", json!({ "variablesReference": 1 })),
+                ("variables", json!({ "variablesReference": 3 })),
+                ("variables", json!({ "variablesReference": 4 })),
+                ("continue", json!({ "threadId": 1 })),
+                ("disconnect", json!({})),
+            ],
+        );
+        assert_eq!(response(&messages, "initialize")["supportsFunctionBreakpoints"], true);
+        let lines = &response(&messages, "setBreakpoints")["breakpoints"];
+        assert_eq!(lines, &json!([{ "verified": true, "line": 1 }, { "verified": false, "line": 99 }]));
+        let functions = &response(&messages, "setFunctionBreakpoints")["breakpoints"];
+        assert_eq!(functions, &json!([{ "verified": true }, { "verified": false }]));
+
+        let frames = &response(&messages, "stackTrace")["stackFrames"];
+        let names: Vec<&str> = frames.as_array().unwrap().iter().map(|frame| frame["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["f", "g", "main"]);
+        assert_eq!(frames[0]["line"], 1);
+        let scopes = &response(&messages, "scopes")["scopes"];
+        assert_eq!(scopes[0]["variablesReference"], 3);
+        assert_eq!(scopes[1]["variablesReference"], 4);
+
+        let variables = variables(&messages);
+        assert_eq!(variables[0], json!([{ "name": "x", "value": "5", "type": "int", "variablesReference": 0 }]));
+        assert_eq!(variables[1], json!([{ "name": "y", "value": "7", "type": "int", "variablesReference": 0 }]));
+        // The caller still holds `y` below the argument it passed
+        assert_eq!(variables[2], json!([{ "name": "[0]", "value": "7", "type": "int", "variablesReference": 0 }]));
+
+        let events = events(&messages);
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["initialized", "output", "stopped", "stopped", "output", "exited", "terminated"]);
+        assert_eq!(events[1].1["output"], "hi\n");
+        assert_eq!(events[2].1["reason"], "breakpoint");
+        assert_eq!(events[4].1["output"], "13\n");
+        assert_eq!(events[5].1["exitCode"], 0);
+    }
+
+    #[test]
+    fn exits_with_the_code_main_returned() {
+        let source = "fn main() int -> 1 2 +;\n";
+        let messages = session("exit_code", source, &[("configurationDone", json!({})), ("disconnect", json!({}))]);
+        let events = events(&messages);
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["exited", "terminated"]);
+        assert_eq!(events[0].1["exitCode"], 3);
+    }
+
+    #[test]
+    fn reports_runtime_errors_when_exiting() {
+        let source = "fn main() void -> \"NO_SUCH_VARIABLE_FOR_BUG\" @env @write;\n";
+        let messages = session("error", source, &[("configurationDone", json!({})), ("disconnect", json!({}))]);
+        let events = events(&messages);
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["output", "exited", "terminated"]);
+        assert!(events[0].1["output"].as_str().unwrap().starts_with("RUNTIME EXCEPTION"));
+        assert_eq!(events[1].1["exitCode"], 1);
+    }
+
+    #[test]
+    fn requests_need_a_launched_program() {
+        let mut input: Vec<u8> = vec![];
+        write_message(&mut input, &json!({ "seq": 1, "type": "request", "command": "stackTrace" })).unwrap();
+        let mut output: Vec<u8> = vec![];
+        serve(input.as_slice(), &mut output).unwrap();
+        let response = read_message(&mut output.as_slice()).unwrap().unwrap();
+        assert_eq!(response["success"], false);
+        assert_eq!(response["message"], "No program was launched");
+    }
+}
@@ -0,0 +1,219 @@
+use super::{Breakpoint, Resume, Session, Stop};
+use crate::bugc::compile;
+use crate::bugc::utils::read_file;
+use crate::repl::format_stack;
+use bug::disassembler::instruction;
+use bug::stdlib::list_natives;
+use bug::vm::frame::Frame;
+use bug::vm::Engine;
+use bug::Program;
+use std::io::{self, BufRead, Write};
+
+const PROMPT: &str = "(debug) ";
+
+const HELP: &str = "\
+  break <fn|line>   pause when a function is called or a line is reached (b)
+  clear <fn|line>   remove a breakpoint
+  breakpoints       list the breakpoints
+  step              run until the next line, entering calls (s)
+  next              run until the next line, stepping over calls (n)
+  finish            run until the current function returns (f)
+  stepi             run a single instruction (si)
+  continue          run until a breakpoint or the end of the program (c)
+  frame             show the current frame: ip, instruction, locals and stack
+  locals            show the locals of the current frame
+  stack             show the operand stack of the current frame
+  backtrace         show every active call (bt)
+  list              show the source around the current line (l)
+  help              show this message
+  quit              leave the debugger (q)";
+
+struct Debugger {
+    file_path: String,
+    source: Vec<String>,
+    /// Names the callees of the instructions shown, the engine only knows their indices
+    listing: Program,
+    session: Session,
+}
+
+/// Starts `file_path` paused at the first instruction of `main` and reads commands from stdin
+pub fn run(file_path: &str) {
+    let program = compile(file_path);
+    let contents = read_file(file_path).unwrap_or_default();
+    let source = contents.lines().map(String::from).collect();
+    let listing = program.clone();
+    let session = match Session::new(Engine::bootstrap(program, list_natives()).with_source(contents)) {
+        Ok(session) => session,
+        Err(err) => {
+            eprintln!("RUNTIME EXCEPTION: {}", err);
+            std::process::exit(err.exit_code());
+        }
+    };
+    let mut debugger = Debugger { file_path: file_path.to_string(), source, listing, session };
+
+    println!("Debugging {}, type `help` for the list of commands", file_path);
+    debugger.show_position();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("{}", PROMPT);
+        io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(err)) => {
+                eprintln!("[Error]: Couldn't read input: {}", err);
+                std::process::exit(1);
+            }
+            None => break,
+        };
+        let line = line.trim();
+        let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match command {
+            "" => {}
+            "q" | "quit" => break,
+            "h" | "help" => println!("{}", HELP),
+            "b" | "break" => debugger.add_breakpoint(argument.trim()),
+            "clear" => debugger.clear_breakpoint(argument.trim()),
+            "breakpoints" => debugger.list_breakpoints(),
+            "s" | "step" => debugger.resume(Resume::StepInto),
+            "n" | "next" => debugger.resume(Resume::StepOver),
+            "f" | "finish" => debugger.resume(Resume::StepOut),
+            "si" | "stepi" => debugger.resume(Resume::Instruction),
+            "c" | "continue" => debugger.resume(Resume::Continue),
+            "frame" => debugger.show_frame(),
+            "locals" => debugger.show_locals(),
+            "stack" => println!("{}", format_stack(debugger.session.engine.operands(0))),
+            "bt" | "backtrace" => debugger.show_backtrace(),
+            "l" | "list" => debugger.list_source(),
+            _ => eprintln!("Unknown command `{}`, try `help`", command),
+        }
+    }
+}
+
+impl Debugger {
+    fn parse_breakpoint(argument: &str) -> Option<Breakpoint> {
+        if argument.is_empty() {
+            eprintln!("Expecting a function name or a line number");
+            return None;
+        }
+        match argument.parse::<usize>() {
+            Ok(line) => Some(Breakpoint::Line(line)),
+            Err(_) => Some(Breakpoint::Function(argument.trim_start_matches('@').to_string())),
+        }
+    }
+
+    fn add_breakpoint(&mut self, argument: &str) {
+        let Some(breakpoint) = Self::parse_breakpoint(argument) else { return };
+        if let Breakpoint::Function(name) = &breakpoint {
+            if !self.session.engine.has_function(name) {
+                eprintln!("No function named `{}`", name);
+                return;
+            }
+        }
+        println!("Breakpoint at {}", breakpoint);
+        if !self.session.breakpoints.contains(&breakpoint) {
+            self.session.breakpoints.push(breakpoint);
+        }
+    }
+
+    fn clear_breakpoint(&mut self, argument: &str) {
+        let Some(breakpoint) = Self::parse_breakpoint(argument) else { return };
+        match self.session.breakpoints.iter().position(|b| *b == breakpoint) {
+            Some(index) => {
+                self.session.breakpoints.remove(index);
+                println!("Removed the breakpoint at {}", breakpoint);
+            }
+            None => eprintln!("No breakpoint at {}", breakpoint),
+        }
+    }
+
+    fn list_breakpoints(&self) {
+        if self.session.breakpoints.is_empty() {
+            println!("No breakpoints");
+        }
+        for breakpoint in &self.session.breakpoints {
+            println!("  {}", breakpoint);
+        }
+    }
+
+    fn resume(&mut self, resume: Resume) {
+        if self.session.finished {
+            eprintln!("The program has finished, `quit` to leave");
+            return;
+        }
+        match self.session.resume(resume) {
+            Stop::Finished => {
+                println!("The program has finished");
+                return;
+            }
+            Stop::Error(err) => {
+                eprintln!("RUNTIME EXCEPTION: {}", err);
+                eprint!("{}", self.session.engine.stack_trace());
+                return;
+            }
+            Stop::Breakpoint(breakpoint) => println!("Breakpoint at {}", breakpoint),
+            Stop::Step => {}
+        }
+        self.show_position();
+    }
+
+    fn show_position(&self) {
+        let frame = self.session.engine.frame();
+        println!("{}", self.describe(frame, frame.ip));
+        if let Some(line) = frame.function.span_at(frame.ip).map(|span| span.line) {
+            self.print_line(line, true);
+        }
+    }
+
+    /// `name at file:line:column` or `name at offset` when the instruction has no source location
+    fn describe(&self, frame: &Frame, offset: usize) -> String {
+        match frame.function.span_at(offset) {
+            Some(span) => format!("`{}` at {}:{}:{}", frame.get_name(), self.file_path, span.line, span.column),
+            None => format!("`{}` at offset {:04}", frame.get_name(), offset),
+        }
+    }
+
+    fn print_line(&self, line: usize, current: bool) {
+        if let Some(text) = self.source.get(line.wrapping_sub(1)) {
+            println!("{} {:>4} | {}", if current { "=>" } else { "  " }, line, text);
+        }
+    }
+
+    fn show_frame(&self) {
+        let frame = self.session.engine.frame();
+        println!("{}", self.describe(frame, frame.ip));
+        let next = frame.function.code.get_at(frame.ip);
+        let next = next.map_or("(none)".to_string(), |opcode| instruction(opcode, &self.listing));
+        println!("  ip      {:04}  {}", frame.ip, next);
+        println!("  locals  {}", format_stack(self.session.engine.locals(0)));
+        println!("  stack   {}", format_stack(self.session.engine.operands(0)));
+    }
+
+    fn show_locals(&self) {
+        let frame = self.session.engine.frame();
+        let locals = self.session.engine.locals(0);
+        if locals.is_empty() {
+            println!("(none)");
+        }
+        for (index, local) in locals.iter().enumerate() {
+            println!("  {}: {}", frame.function.local_name(index), format_stack(std::slice::from_ref(local)));
+        }
+    }
+
+    fn show_backtrace(&self) {
+        for (index, (frame, offset)) in self.session.frames().into_iter().enumerate() {
+            println!("#{} {}", index, self.describe(frame, offset));
+        }
+    }
+
+    fn list_source(&self) {
+        let frame = self.session.engine.frame();
+        let Some(line) = frame.function.span_at(frame.ip).map(|span| span.line) else {
+            eprintln!("No source location for the current instruction");
+            return;
+        };
+        for number in line.saturating_sub(3).max(1)..=line + 3 {
+            self.print_line(number, number == line);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+pub mod cli;
+
+use bug::vm::frame::Frame;
+use bug::vm::{Engine, RuntimeError};
+use std::cmp::Ordering;
+use std::fmt;
+
+pub use cli::run;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    Function(String),
+    Line(usize),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Function(name) => write!(f, "function `{}`", name),
+            Self::Line(line) => write!(f, "line {}", line),
+        }
+    }
+}
+
+/// How far `Session::resume` lets the program run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resume {
+    Instruction,
+    StepInto,
+    StepOver,
+    StepOut,
+    Continue,
+}
+
+/// Why `Session::resume` gave control back
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    Step,
+    Breakpoint(Breakpoint),
+    Finished,
+    /// The program stopped on a runtime error, the engine is left where it happened
+    Error(RuntimeError),
+}
+
+/// Where the engine is paused: call depth, function and source line of the next instruction
+#[derive(Debug, Clone, PartialEq)]
+struct Position {
+    depth: usize,
+    function: String,
+    line: Option<usize>,
+}
+
+/// A program paused between instructions, with the breakpoints set on it
+pub struct Session {
+    pub engine: Engine,
+    pub breakpoints: Vec<Breakpoint>,
+    pub finished: bool,
+}
+
+impl Session {
+    /// Starts the program of `engine` paused at the first instruction of `main`
+    pub fn new(mut engine: Engine) -> Result<Self, RuntimeError> {
+        engine.start()?;
+        Ok(Self { engine, breakpoints: vec![], finished: false })
+    }
+
+    /// Every active call, innermost first, with the offset of the instruction it is at
+    pub fn frames(&self) -> Vec<(&Frame, usize)> {
+        let current = self.engine.frame();
+        let mut frames = vec![(current, current.ip)];
+        // Callers are paused right after their `INVOKE`
+        for frame in self.engine.frame_stack.inner.iter().rev() {
+            frames.push((frame, frame.ip.saturating_sub(1)));
+        }
+        frames
+    }
+
+    /// Whether any instruction of the program comes from `line`
+    pub fn has_line(&self, line: usize) -> bool {
+        self.engine.functions().any(|function| function.spans.iter().any(|span| span.line == line))
+    }
+
+    pub fn resume(&mut self, resume: Resume) -> Stop {
+        if self.finished {
+            return Stop::Finished;
+        }
+        let start = self.position();
+        let mut previous = start.clone();
+        loop {
+            match self.engine.step() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.finished = true;
+                    return Stop::Finished;
+                }
+                Err(err) => {
+                    self.finished = true;
+                    return Stop::Error(err);
+                }
+            }
+            let current = self.position();
+            if let Some(breakpoint) = self.breakpoint_hit(&previous, &current) {
+                return Stop::Breakpoint(breakpoint.clone());
+            }
+            let new_line = current.line.is_some() && current.line != start.line;
+            let should_pause = match resume {
+                Resume::Instruction => true,
+                Resume::StepInto => current.depth != start.depth || new_line,
+                Resume::StepOver => current.depth < start.depth || (current.depth == start.depth && new_line),
+                Resume::StepOut => current.depth < start.depth,
+                Resume::Continue => false,
+            };
+            if should_pause {
+                return Stop::Step;
+            }
+            previous = current;
+        }
+    }
+
+    /// The line of the `INVOKE` the current frame last ran
+    fn call_line(&self) -> Option<usize> {
+        let frame = self.engine.frame();
+        frame.function.span_at(frame.ip.checked_sub(1)?).map(|span| span.line)
+    }
+
+    fn position(&self) -> Position {
+        let frame = self.engine.frame();
+        Position {
+            depth: self.engine.depth(),
+            function: frame.get_name().to_string(),
+            line: frame.function.span_at(frame.ip).map(|span| span.line),
+        }
+    }
+
+    fn breakpoint_hit(&self, previous: &Position, current: &Position) -> Option<&Breakpoint> {
+        let entered = current.depth > previous.depth;
+        let line_changed = match current.depth.cmp(&previous.depth) {
+            Ordering::Greater => true,
+            Ordering::Equal => current.line != previous.line,
+            // Returning to a caller only reaches a line when the call was on another one
+            Ordering::Less => current.line != self.call_line(),
+        };
+        self.breakpoints.iter().find(|breakpoint| match breakpoint {
+            Breakpoint::Function(name) => entered && *name == current.function,
+            Breakpoint::Line(line) => line_changed && current.line == Some(*line),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Breakpoint, Resume, Session, Stop};
+    use crate::bugc::try_compile;
+    use bug::stdlib::list_natives;
+    use bug::vm::{Engine, RuntimeError};
+    use bug::Object;
+
+    const SOURCE: &str = "fn twice(int n) int ->\n    n n +;\nfn main() int ->\n    3 @twice\n    @twice;\n";
+
+    fn session(source: &str, breakpoints: Vec<Breakpoint>) -> Session {
+        let program = try_compile("debugged.bug", source).unwrap();
+        let mut session = Session::new(Engine::bootstrap(program, list_natives())).unwrap();
+        session.breakpoints = breakpoints;
+        session
+    }
+
+    /// Function, line and depth the session is paused at
+    fn at(session: &Session) -> (String, Option<usize>, usize) {
+        let position = session.position();
+        (position.function, position.line, position.depth)
+    }
+
+    fn paused_at(function: &str, line: usize, depth: usize) -> (String, Option<usize>, usize) {
+        (function.to_string(), Some(line), depth)
+    }
+
+    #[test]
+    fn steps_into_over_and_out_of_calls() {
+        let mut session = session(SOURCE, vec![]);
+        assert_eq!(at(&session), paused_at("main", 4, 0));
+        assert_eq!(session.resume(Resume::StepInto), Stop::Step);
+        assert_eq!(at(&session), paused_at("twice", 2, 1));
+        assert_eq!(session.resume(Resume::StepOut), Stop::Step);
+        assert_eq!(at(&session), paused_at("main", 5, 0));
+        // The second call runs without pausing, `RETURN` belongs to the line of the function's name
+        assert_eq!(session.resume(Resume::StepOver), Stop::Step);
+        assert_eq!(at(&session), paused_at("main", 3, 0));
+        assert_eq!(session.resume(Resume::StepOver), Stop::Finished);
+        assert_eq!(session.engine.result(), Some(&Object::Integer(12)));
+        assert_eq!(session.resume(Resume::Continue), Stop::Finished);
+    }
+
+    #[test]
+    fn steps_single_instructions() {
+        let mut session = session(SOURCE, vec![]);
+        assert_eq!(session.resume(Resume::Instruction), Stop::Step);
+        assert_eq!((at(&session), session.engine.frame().ip), (paused_at("main", 4, 0), 1));
+        assert_eq!(session.resume(Resume::Instruction), Stop::Step);
+        assert_eq!((at(&session), session.engine.frame().ip), (paused_at("twice", 2, 1), 0));
+    }
+
+    #[test]
+    fn stops_on_every_call_of_a_function() {
+        let mut session = session(SOURCE, vec![Breakpoint::Function("twice".to_string())]);
+        let breakpoint = Stop::Breakpoint(Breakpoint::Function("twice".to_string()));
+        assert_eq!(session.resume(Resume::Continue), breakpoint);
+        assert_eq!(session.engine.locals(0), [Object::Integer(3)]);
+        assert_eq!(session.resume(Resume::Continue), breakpoint);
+        assert_eq!(session.engine.locals(0), [Object::Integer(6)]);
+        assert_eq!(session.resume(Resume::Continue), Stop::Finished);
+    }
+
+    #[test]
+    fn stops_at_lines_reached_by_returning() {
+        let mut session = session(SOURCE, vec![Breakpoint::Line(5)]);
+        assert_eq!(session.resume(Resume::Continue), Stop::Breakpoint(Breakpoint::Line(5)));
+        assert_eq!(at(&session), paused_at("main", 5, 0));
+        assert_eq!(session.resume(Resume::Continue), Stop::Finished);
+    }
+
+    #[test]
+    fn returning_to_the_line_of_the_call_is_not_reaching_it() {
+        let source = "fn twice(int n) int ->\n    n n +;\nfn main() int ->\n    3 @twice 1 +;\n";
+        let mut session = session(source, vec![Breakpoint::Line(4)]);
+        assert_eq!(session.resume(Resume::Continue), Stop::Finished);
+    }
+
+    #[test]
+    fn stops_on_runtime_errors() {
+        let mut session = session("fn main() int ->\n    2147483647 1 +;\n", vec![]);
+        let overflow = RuntimeError::IntegerOverflow { lhs: i32::MAX, rhs: 1 };
+        assert_eq!(session.resume(Resume::Continue), Stop::Error(overflow));
+        assert_eq!(session.resume(Resume::Continue), Stop::Finished);
+    }
+}
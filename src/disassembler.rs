@@ -0,0 +1,207 @@
+use crate::bytecode::Opcode;
+use crate::{DefinedFn, Object, Program};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+/// Human readable listing of every constant and function of `program`, in the format the assembler reads
+pub fn disassemble(program: &Program) -> String {
+    let mut out = String::new();
+    for (index, object) in program.pool.entries.iter().enumerate() {
+        out.push_str(&format!("const #{} {} {}\n", index, object_type(object), literal(object)));
+    }
+    for function in &program.fns {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&disassemble_function(function, program));
+    }
+    out
+}
+
+/// Listing of a single function, `program` is used to show the constants and the callees
+pub fn disassemble_function(function: &DefinedFn, program: &Program) -> String {
+    let labels: BTreeSet<usize> = function.code.code.iter().filter_map(Opcode::jump_target).collect();
+    let mut out = format!("fn {} arity={} locals={}\n", function.name, function.arity, function.max_locals);
+    for (offset, opcode) in function.code.code.iter().enumerate() {
+        if labels.contains(&offset) {
+            out.push_str(&format!("{}:\n", label(offset)));
+        }
+        out.push_str(&format!("    {:04}  {}\n", offset, instruction(opcode, program)));
+    }
+    // Jumps past the last instruction still need their label to be defined
+    for offset in labels.range(function.code.code.len()..) {
+        out.push_str(&format!("{}:\n", label(*offset)));
+    }
+    out.push_str("end\n");
+    out
+}
+
+/// An opcode with its operands decoded, e.g. `LDC #0  ; "Hello"`, `INVOKE sum` or `JUMP L0007`
+pub fn instruction(opcode: &Opcode, program: &Program) -> String {
+    let mnemonic = opcode.mnemonic();
+    match opcode {
+        Opcode::LDC(index) => match program.pool.get_by_index(*index) {
+            Some(object) => format!("{} #{}  ; {}", mnemonic, index, literal(object)),
+            None => format!("{} #{}  ; out of range", mnemonic, index),
+        },
+        Opcode::INVOKE(index) => match program.fns.get(*index) {
+            Some(function) => format!("{} {}", mnemonic, function.name),
+            None => format!("{} #{}  ; out of range", mnemonic, index),
+        },
+        Opcode::INVOKE_NATIVE(index) => match program.natives.get(*index) {
+            Some(name) => format!("{} {}", mnemonic, name),
+            None => format!("{} #{}  ; out of range", mnemonic, index),
+        },
+        Opcode::LLOAD(index) | Opcode::LSTORE(index) => format!("{} {}", mnemonic, index),
+        Opcode::IPUSH(integer) => format!("{} {}", mnemonic, integer),
+        Opcode::JUMP(offset) | Opcode::JUMPNOTIF(offset) => format!("{} {}", mnemonic, label(*offset)),
+        Opcode::NOP | Opcode::IADD | Opcode::ICMPGT | Opcode::RETURN => mnemonic.to_string(),
+    }
+}
+
+/// Machine readable variant of `disassemble`
+pub fn disassemble_json(program: &Program) -> Value {
+    let pool: Vec<Value> = program
+        .pool
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(index, object)| json!({ "index": index, "type": object_type(object), "value": object_value(object) }))
+        .collect();
+    let functions: Vec<Value> = program
+        .fns
+        .iter()
+        .map(|function| {
+            let code: Vec<Value> = function
+                .code
+                .code
+                .iter()
+                .enumerate()
+                .map(|(offset, opcode)| instruction_json(offset, opcode, program))
+                .collect();
+            json!({ "name": function.name, "arity": function.arity, "max_locals": function.max_locals, "code": code })
+        })
+        .collect();
+    json!({ "pool": pool, "natives": program.natives, "functions": functions })
+}
+
+fn instruction_json(offset: usize, opcode: &Opcode, program: &Program) -> Value {
+    let mut instruction = json!({ "offset": offset, "opcode": opcode.mnemonic() });
+    match opcode {
+        Opcode::LDC(index) => {
+            instruction["index"] = json!(index);
+            instruction["constant"] = program.pool.get_by_index(*index).map_or(Value::Null, object_value);
+        }
+        Opcode::INVOKE(index) => {
+            instruction["index"] = json!(index);
+            instruction["function"] = program.fns.get(*index).map_or(Value::Null, |function| json!(function.name));
+        }
+        Opcode::INVOKE_NATIVE(index) => {
+            instruction["index"] = json!(index);
+            instruction["native"] = program.natives.get(*index).map_or(Value::Null, |name| json!(name));
+        }
+        Opcode::LLOAD(index) | Opcode::LSTORE(index) => instruction["local"] = json!(index),
+        Opcode::IPUSH(integer) => instruction["value"] = json!(integer),
+        Opcode::JUMP(offset) | Opcode::JUMPNOTIF(offset) => instruction["target"] = json!(offset),
+        Opcode::NOP | Opcode::IADD | Opcode::ICMPGT | Opcode::RETURN => {}
+    }
+    instruction
+}
+
+pub fn label(offset: usize) -> String {
+    format!("L{:04}", offset)
+}
+
+fn object_type(object: &Object) -> &'static str {
+    match object {
+        Object::Integer(_) => "int",
+        Object::String(_) => "str",
+        Object::Boolean(_) => "bool",
+        Object::List(_) => "list",
+    }
+}
+
+fn literal(object: &Object) -> String {
+    match object {
+        Object::String(string) => format!("{:?}", string),
+        object => object.to_string(),
+    }
+}
+
+/// `object` as a JSON value of the matching type
+pub fn object_value(object: &Object) -> Value {
+    match object {
+        Object::Integer(integer) => json!(integer),
+        Object::String(string) => json!(string),
+        Object::Boolean(boolean) => json!(boolean),
+        Object::List(objects) => Value::Array(objects.iter().map(object_value).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, disassemble_json};
+    use crate::assembler::assemble;
+    use serde_json::json;
+
+    const SOURCE: &str = r#"
+const str "hi\n"
+const list [1, "a"]
+
+fn main arity=0 locals=1
+    LDC #0
+    INVOKE_NATIVE write
+    IPUSH 2
+    LSTORE 0
+    LLOAD 0
+    IPUSH 1
+    ICMPGT
+    JUMPNOTIF done
+    INVOKE main
+done:
+    RETURN
+end
+"#;
+
+    #[test]
+    fn lists_constants_and_functions() {
+        let expected = r#"const #0 str "hi\n"
+const #1 list [1, "a"]
+
+fn main arity=0 locals=1
+    0000  LDC #0  ; "hi\n"
+    0001  INVOKE_NATIVE write
+    0002  IPUSH 2
+    0003  LSTORE 0
+    0004  LLOAD 0
+    0005  IPUSH 1
+    0006  ICMPGT
+    0007  JUMPNOTIF L0009
+    0008  INVOKE main
+L0009:
+    0009  RETURN
+end
+"#;
+        assert_eq!(disassemble(&assemble(SOURCE).unwrap()), expected);
+    }
+
+    #[test]
+    fn labels_jumps_past_the_end() {
+        let listing = disassemble(&assemble("fn main arity=0 locals=0\n    JUMP end\nend:\nend\n").unwrap());
+        assert_eq!(listing, "fn main arity=0 locals=0\n    0000  JUMP L0001\nL0001:\nend\n");
+    }
+
+    #[test]
+    fn json_decodes_the_operands() {
+        let program = disassemble_json(&assemble(SOURCE).unwrap());
+        assert_eq!(program["pool"][1], json!({ "index": 1, "type": "list", "value": [1, "a"] }));
+        assert_eq!(program["natives"], json!(["write"]));
+        let code = &program["functions"][0]["code"];
+        assert_eq!(code[0], json!({ "offset": 0, "opcode": "LDC", "index": 0, "constant": "hi\n" }));
+        assert_eq!(code[1], json!({ "offset": 1, "opcode": "INVOKE_NATIVE", "index": 0, "native": "write" }));
+        assert_eq!(code[3], json!({ "offset": 3, "opcode": "LSTORE", "local": 0 }));
+        assert_eq!(code[7], json!({ "offset": 7, "opcode": "JUMPNOTIF", "target": 9 }));
+        assert_eq!(code[8], json!({ "offset": 8, "opcode": "INVOKE", "index": 0, "function": "main" }));
+        assert_eq!(program["functions"][0]["max_locals"], 1);
+    }
+}
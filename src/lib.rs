@@ -1,12 +1,18 @@
+pub mod assembler;
+pub mod binary;
 pub mod bytecode;
+pub mod disassembler;
+pub mod highlighter;
+pub mod span;
 pub mod stdlib;
 pub mod utils;
+pub mod vm;
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
 use bytecode::ByteCodeStream;
 use core::fmt;
+use span::Span;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Type {
@@ -14,6 +20,7 @@ pub enum Type {
     String,
     Integer,
     Boolean,
+    List(Box<Type>),
 }
 
 impl fmt::Display for Type {
@@ -23,6 +30,7 @@ impl fmt::Display for Type {
             Self::String => write!(f, "str"),
             Self::Integer => write!(f, "int"),
             Self::Boolean => write!(f, "bool"),
+            Self::List(element_type) => write!(f, "[{}]", element_type),
         }
     }
 }
@@ -32,11 +40,13 @@ pub struct FunctionPrototype {
     pub arity: usize,
     pub return_type: Type,
     pub parameters_types: Vec<Type>,
+    /// The last parameter is a template string, the function also takes a value for each of its placeholders
+    pub template: bool,
 }
 
 impl FunctionPrototype {
     pub fn new(arity: usize, return_type: Type, parameters_types: Vec<Type>) -> Self {
-        Self { arity, return_type, parameters_types }
+        Self { arity, return_type, parameters_types, template: false }
     }
 }
 
@@ -45,6 +55,19 @@ pub enum Object {
     Integer(i32),
     String(String),
     Boolean(bool),
+    List(Vec<Object>),
+}
+
+impl Object {
+    pub fn get_type(&self) -> Type {
+        match self {
+            Self::Integer(_) => Type::Integer,
+            Self::String(_) => Type::String,
+            Self::Boolean(_) => Type::Boolean,
+            // An empty list doesn't tell what it holds
+            Self::List(objects) => Type::List(Box::new(objects.first().map_or(Type::Void, |o| o.get_type()))),
+        }
+    }
 }
 
 impl fmt::Display for Object {
@@ -53,6 +76,16 @@ impl fmt::Display for Object {
             Self::Integer(x) => write!(f, "{}", x),
             Self::String(x) => write!(f, "{}", x),
             Self::Boolean(x) => write!(f, "{}", x),
+            Self::List(objects) => {
+                let objects: Vec<String> = objects
+                    .iter()
+                    .map(|o| match o {
+                        Self::String(string) => format!("{:?}", string),
+                        o => o.to_string(),
+                    })
+                    .collect();
+                write!(f, "[{}]", objects.join(", "))
+            }
         }
     }
 }
@@ -60,32 +93,106 @@ impl fmt::Display for Object {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Program {
     pub pool: Pool,
-    pub fns: HashMap<String, DefinedFn>,
+    /// Functions in the order `INVOKE` numbers them
+    pub fns: Vec<DefinedFn>,
+    /// Names of the natives the program calls in the order `INVOKE_NATIVE` numbers them, bound when loaded
+    pub natives: Vec<String>,
+    /// Path of the source file the program was compiled from, empty when unknown
+    pub file_name: String,
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Program {
     pub fn new() -> Self {
-        Self { pool: Pool::make(), fns: HashMap::new() }
+        Self { pool: Pool::make(), fns: vec![], natives: vec![], file_name: String::new() }
+    }
+
+    /// Index of the function `name`, the operand of the `INVOKE`s calling it
+    pub fn function_index(&self, name: &str) -> Option<usize> {
+        self.fns.iter().position(|function| function.name == name)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&DefinedFn> {
+        self.fns.iter().find(|function| function.name == name)
+    }
+
+    /// Index of the function `name`, numbering it with an empty body when it isn't defined yet
+    pub fn declare_function(&mut self, name: &str) -> usize {
+        self.function_index(name).unwrap_or_else(|| {
+            self.fns.push(DefinedFn::default().with_name(name));
+            self.fns.len() - 1
+        })
+    }
+
+    /// Index of the native `name`, the operand of the `INVOKE_NATIVE`s calling it
+    pub fn declare_native(&mut self, name: &str) -> usize {
+        self.natives.iter().position(|native| native == name).unwrap_or_else(|| {
+            self.natives.push(name.to_string());
+            self.natives.len() - 1
+        })
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DefinedFn {
+    /// Source name, calls refer to the function by its index in the program
+    pub name: String,
     pub start_line: usize,
     pub arity: usize,
     pub code: ByteCodeStream,
     pub max_locals: usize,
+    /// Source location of every instruction, indexed by offset. Empty when the source is unknown
+    pub spans: Vec<Span>,
+    /// Source name of every local, indexed like the locals. Empty when the names are unknown
+    pub local_names: Vec<String>,
 }
 
 impl DefinedFn {
     pub fn new(start_line: usize, arity: usize, code: ByteCodeStream, max_locals: usize) -> Self {
-        Self { start_line, arity, code, max_locals }
+        Self { name: String::new(), start_line, arity, code, max_locals, spans: vec![], local_names: vec![] }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn with_spans(mut self, spans: Vec<Span>) -> Self {
+        self.spans = spans;
+        self
+    }
+
+    pub fn with_local_names(mut self, local_names: Vec<String>) -> Self {
+        self.local_names = local_names;
+        self
+    }
+
+    /// Name of the local at `index`, or a made up one when the compiler didn't record it
+    pub fn local_name(&self, index: usize) -> String {
+        self.local_names.get(index).cloned().unwrap_or_else(|| format!("local{}", index))
+    }
+
+    pub fn span_at(&self, offset: usize) -> Option<&Span> {
+        self.spans.get(offset)
     }
 }
 
 impl Default for DefinedFn {
     fn default() -> Self {
-        Self { start_line: 0, arity: 0, code: ByteCodeStream::empty(), max_locals: 0 }
+        Self {
+            name: String::new(),
+            start_line: 0,
+            arity: 0,
+            code: ByteCodeStream::empty(),
+            max_locals: 0,
+            spans: vec![],
+            local_names: vec![],
+        }
     }
 }
 
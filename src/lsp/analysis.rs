@@ -0,0 +1,209 @@
+use crate::bugc::ast::*;
+use crate::bugc::checker::{Checker, StackSnapshot};
+use crate::bugc::lexer::Lexer;
+use crate::bugc::parser::Parser;
+use bug::span::Span;
+use bug::stdlib::{list_natives, prototypes};
+use bug::{FunctionPrototype, Type};
+
+/// Everything the language server knows about one version of a document
+pub struct Analysis {
+    pub diagnostics: Vec<(Span, String)>,
+    pub snapshots: Vec<StackSnapshot>,
+    pub functions: Vec<FunctionInfo>,
+    pub natives: Vec<(String, FunctionPrototype)>,
+    pub references: Vec<Reference>,
+    /// Whether the document parsed, otherwise there is nothing but the parser's diagnostic
+    pub parsed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    pub name: String,
+    /// Span of the function's name in its definition
+    pub span: Span,
+    /// Span from the function's name to the `;` closing its body
+    pub extent: Span,
+    pub prototype: FunctionPrototype,
+    pub locals: Vec<LocalInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalInfo {
+    pub name: String,
+    pub typ: Type,
+    pub span: Span,
+}
+
+/// A name used inside an expression, either `@name` or a plain identifier
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    pub is_call: bool,
+}
+
+pub enum Definition<'a> {
+    Function(&'a FunctionInfo),
+    Native(&'a str, &'a FunctionPrototype),
+    Local(&'a LocalInfo),
+}
+
+impl Analysis {
+    pub fn analyze(uri: &str, text: &str) -> Self {
+        let mut natives: Vec<(String, FunctionPrototype)> = prototypes(&list_natives()).into_iter().collect();
+        natives.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+        let mut analysis = Self {
+            diagnostics: vec![],
+            snapshots: vec![],
+            functions: vec![],
+            natives,
+            references: vec![],
+            parsed: false,
+        };
+
+        let mut lexer = Lexer::new(text);
+        let mut ast = match Parser::new(uri, text, &mut lexer).parse() {
+            Ok(ast) => ast,
+            Err(err) => {
+                analysis.diagnostics.push((err.span, err.reason));
+                return analysis;
+            }
+        };
+        analysis.parsed = true;
+
+        let mut checker = Checker::new(uri, text, prototypes(&list_natives()));
+        if let Some(diagnostics) = checker.check(&mut ast) {
+            for diagnostic in &diagnostics.diagnostics {
+                analysis.diagnostics.push((diagnostic.span.clone(), diagnostic.message.clone()));
+            }
+        }
+        analysis.snapshots = checker.snapshots().to_vec();
+
+        for statement in &ast {
+            match statement {
+                Statement::Function(function) => analysis.collect_function(function),
+                Statement::Expression(expression) => analysis.collect_expression(expression),
+                _ => {}
+            }
+        }
+        analysis
+    }
+
+    pub fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references.iter().find(|reference| contains(&reference.span, offset))
+    }
+
+    pub fn function_defined_at(&self, offset: usize) -> Option<&FunctionInfo> {
+        self.functions.iter().find(|function| contains(&function.span, offset))
+    }
+
+    pub fn local_defined_at(&self, offset: usize) -> Option<&LocalInfo> {
+        self.enclosing_function(offset)?.locals.iter().find(|local| contains(&local.span, offset))
+    }
+
+    pub fn enclosing_function(&self, offset: usize) -> Option<&FunctionInfo> {
+        self.functions.iter().find(|function| contains(&function.extent, offset))
+    }
+
+    pub fn resolve(&self, reference: &Reference) -> Option<Definition<'_>> {
+        if !reference.is_call {
+            let function = self.enclosing_function(reference.span.start)?;
+            return function.locals.iter().find(|local| local.name == reference.name).map(Definition::Local);
+        }
+        // Like the checker, a call only sees the functions defined before it, which shadow natives
+        let defined = |function: &&FunctionInfo| function.span.start <= reference.span.start;
+        if let Some(function) = self.functions.iter().filter(defined).find(|function| function.name == reference.name) {
+            return Some(Definition::Function(function));
+        }
+        self.natives
+            .iter()
+            .find(|(name, _)| *name == reference.name)
+            .map(|(name, prototype)| Definition::Native(name, prototype))
+    }
+
+    /// The innermost expression covering `offset` and the stack right after it
+    pub fn snapshot_at(&self, offset: usize) -> Option<&StackSnapshot> {
+        self.snapshots
+            .iter()
+            .filter(|snapshot| contains(&snapshot.span, offset))
+            .min_by_key(|snapshot| snapshot.span.end - snapshot.span.start)
+    }
+
+    fn collect_function(&mut self, function: &StatementFunction) {
+        let identifier = &function.identifier;
+        let extent =
+            Span::new(identifier.span.line, identifier.span.column, identifier.span.start, function.body.span.end);
+        let mut locals: Vec<LocalInfo> = function
+            .parameters
+            .parameters
+            .iter()
+            .map(|p| LocalInfo {
+                name: p.identifier.label.clone(),
+                typ: p.typ.clone(),
+                span: p.identifier.span.clone(),
+            })
+            .collect();
+        for statement in &function.body.statements {
+            match statement {
+                Statement::Variable(v) => locals.push(LocalInfo {
+                    name: v.identifier.label.clone(),
+                    typ: v.typ.clone(),
+                    span: v.identifier.span.clone(),
+                }),
+                Statement::Expression(expression) => self.collect_expression(expression),
+                _ => {}
+            }
+        }
+        let parameters_types: Vec<Type> = function.parameters.parameters.iter().map(|p| p.typ.clone()).collect();
+        let prototype = FunctionPrototype::new(parameters_types.len(), function.return_type.clone(), parameters_types);
+        self.functions.push(FunctionInfo {
+            name: identifier.label.clone(),
+            span: identifier.span.clone(),
+            extent,
+            prototype,
+            locals,
+        });
+    }
+
+    fn collect_expression(&mut self, expression: &StatementExpression) {
+        match expression {
+            StatementExpression::Call(call) => self.references.push(Reference {
+                name: call.identifier.label.clone(),
+                span: call.span.clone(),
+                is_call: true,
+            }),
+            StatementExpression::Identifier(identifier) => self.references.push(Reference {
+                name: identifier.label.clone(),
+                span: identifier.span.clone(),
+                is_call: false,
+            }),
+            StatementExpression::Ternary(ternary) => {
+                self.collect_expression(&ternary.consequence);
+                self.collect_expression(&ternary.alternative);
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn signature(name: &str, prototype: &FunctionPrototype) -> String {
+    let parameters: Vec<String> = if prototype.template {
+        vec!["any...".to_string(), "str template".to_string()]
+    } else if prototype.parameters_types.len() == prototype.arity {
+        prototype.parameters_types.iter().map(|typ| typ.to_string()).collect()
+    } else {
+        (0..prototype.arity).map(|_| "any".to_string()).collect()
+    };
+    format!("fn {}({}) {}", name, parameters.join(", "), prototype.return_type)
+}
+
+pub fn format_stack(stack: &[Type]) -> String {
+    if stack.is_empty() {
+        return "(empty)".to_string();
+    }
+    stack.iter().map(|typ| typ.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+fn contains(span: &Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
@@ -0,0 +1,56 @@
+use serde_json::{json, Value};
+
+/// Converts between the byte offsets of a `Span` and the line/UTF-16 positions LSP clients speak
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        for (offset, chr) in text.char_indices() {
+            if chr == '\n' {
+                line_starts.push(offset + 1);
+            }
+        }
+        Self { text, line_starts }
+    }
+
+    pub fn position(&self, offset: usize) -> Value {
+        let offset = self.clamp(offset);
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let character: usize = self.text[self.line_starts[line]..offset].chars().map(char::len_utf16).sum();
+        json!({ "line": line, "character": character })
+    }
+
+    /// `end` is inclusive like in `Span`
+    pub fn range(&self, start: usize, end: usize) -> Value {
+        let end = (end + 1).max(start);
+        json!({ "start": self.position(start), "end": self.position(end) })
+    }
+
+    pub fn offset(&self, position: &Value) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let character = position["character"].as_u64().unwrap_or(0) as usize;
+        let Some(line_start) = self.line_starts.get(line) else { return self.text.len() };
+        let mut offset = *line_start;
+        let mut units = 0;
+        for chr in self.text[*line_start..].chars() {
+            if units >= character || chr == '\n' {
+                break;
+            }
+            units += chr.len_utf16();
+            offset += chr.len_utf8();
+        }
+        offset
+    }
+
+    fn clamp(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
@@ -0,0 +1,390 @@
+mod analysis;
+mod line_index;
+pub mod transport;
+
+use analysis::{format_stack, signature, Analysis, Definition, FunctionInfo};
+use line_index::LineIndex;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use transport::{read_message, write_message};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_REQUEST: i64 = -32600;
+
+const SEVERITY_ERROR: u8 = 1;
+const SYMBOL_KIND_FUNCTION: u8 = 12;
+const COMPLETION_KIND_FUNCTION: u8 = 3;
+
+/// Runs the language server over stdin/stdout until the client sends `exit`
+pub fn run() {
+    let stdin = io::stdin();
+    if let Err(err) = serve(stdin.lock(), io::stdout()) {
+        eprintln!("[Error]: Language server stopped: {}", err);
+        std::process::exit(1);
+    }
+}
+
+pub fn serve<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<()> {
+    let mut server = Server::new(output);
+    while let Some(message) = read_message(&mut input)? {
+        if !server.handle(message)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct Document {
+    text: String,
+    /// Functions from the last version that parsed, so `@` completion keeps working while typing
+    functions: Vec<FunctionInfo>,
+}
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>,
+}
+
+impl<W: Write> Server<W> {
+    fn new(output: W) -> Self {
+        Self { output, documents: HashMap::new() }
+    }
+
+    /// Returns `false` once the client asked the server to exit
+    fn handle(&mut self, message: Value) -> io::Result<bool> {
+        let method = message["method"].as_str().unwrap_or_default().to_string();
+        let params = &message["params"];
+        let Some(id) = message.get("id").cloned() else {
+            return self.handle_notification(&method, params);
+        };
+        let result = match method.as_str() {
+            "initialize" => Ok(self.initialize()),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "textDocument/documentSymbol" => Ok(self.document_symbols(params)),
+            "" => Err((INVALID_REQUEST, "Request without a method".to_string())),
+            _ => Err((METHOD_NOT_FOUND, format!("Unhandled method `{}`", method))),
+        };
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => {
+                json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+            }
+        };
+        write_message(&mut self.output, &response)?;
+        Ok(true)
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Value) -> io::Result<bool> {
+        match method {
+            "exit" => return Ok(false),
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.update_document(uri, text.to_string())?;
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                // We only advertise full synchronization, so the last change holds the whole text
+                if let Some(text) = params["contentChanges"].as_array().and_then(|changes| changes.last()) {
+                    self.update_document(uri, text["text"].as_str().unwrap_or_default().to_string())?;
+                }
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.documents.remove(uri);
+                self.publish_diagnostics(uri, json!([]))?;
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn initialize(&self) -> Value {
+        json!({
+            "capabilities": {
+                "textDocumentSync": 1,
+                "hoverProvider": true,
+                "definitionProvider": true,
+                "documentSymbolProvider": true,
+                "completionProvider": { "triggerCharacters": ["@"] },
+            },
+            "serverInfo": { "name": "bug", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    fn update_document(&mut self, uri: &str, text: String) -> io::Result<()> {
+        let analysis = Analysis::analyze(uri, &text);
+        let index = LineIndex::new(&text);
+        let diagnostics: Vec<Value> = analysis
+            .diagnostics
+            .iter()
+            .map(|(span, message)| {
+                json!({
+                    "range": index.range(span.start, span.end),
+                    "severity": SEVERITY_ERROR,
+                    "source": "bug",
+                    "message": message,
+                })
+            })
+            .collect();
+        let previous = self.documents.remove(uri).map(|document| document.functions).unwrap_or_default();
+        let functions = if analysis.parsed { analysis.functions } else { previous };
+        self.documents.insert(uri.to_string(), Document { text, functions });
+        self.publish_diagnostics(uri, Value::Array(diagnostics))
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str, diagnostics: Value) -> io::Result<()> {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        });
+        write_message(&mut self.output, &notification)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((uri, document)) = self.document(params) else { return Value::Null };
+        let index = LineIndex::new(&document.text);
+        let offset = index.offset(&params["position"]);
+        let analysis = Analysis::analyze(uri, &document.text);
+
+        let mut lines: Vec<String> = vec![];
+        let mut range = None;
+        if let Some(reference) = analysis.reference_at(offset) {
+            range = Some(index.range(reference.span.start, reference.span.end));
+            match analysis.resolve(reference) {
+                Some(Definition::Function(function)) => lines.push(signature(&function.name, &function.prototype)),
+                Some(Definition::Native(name, prototype)) => lines.push(signature(name, prototype)),
+                Some(Definition::Local(local)) => lines.push(format!("{}: {}", local.name, local.typ)),
+                None => {}
+            }
+        } else if let Some(function) = analysis.function_defined_at(offset) {
+            range = Some(index.range(function.span.start, function.span.end));
+            lines.push(signature(&function.name, &function.prototype));
+        } else if let Some(local) = analysis.local_defined_at(offset) {
+            range = Some(index.range(local.span.start, local.span.end));
+            lines.push(format!("{}: {}", local.name, local.typ));
+        }
+
+        let mut contents = String::new();
+        if !lines.is_empty() {
+            contents.push_str(&format!("```bug\n{}\n```\n", lines.join("\n")));
+        }
+        if let Some(snapshot) = analysis.snapshot_at(offset) {
+            range.get_or_insert_with(|| index.range(snapshot.span.start, snapshot.span.end));
+            contents.push_str(&format!("Stack: `{}`", format_stack(&snapshot.stack)));
+        }
+        if contents.is_empty() {
+            return Value::Null;
+        }
+        json!({ "contents": { "kind": "markdown", "value": contents }, "range": range })
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let Some((uri, document)) = self.document(params) else { return Value::Null };
+        let index = LineIndex::new(&document.text);
+        let offset = index.offset(&params["position"]);
+        let analysis = Analysis::analyze(uri, &document.text);
+        let span = match analysis.reference_at(offset).and_then(|reference| analysis.resolve(reference)) {
+            Some(Definition::Function(function)) => &function.span,
+            Some(Definition::Local(local)) => &local.span,
+            // Natives have no source to jump to
+            Some(Definition::Native(..)) | None => return Value::Null,
+        };
+        json!({ "uri": uri, "range": index.range(span.start, span.end) })
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let Some((uri, document)) = self.document(params) else { return Value::Null };
+        let index = LineIndex::new(&document.text);
+        let offset = index.offset(&params["position"]);
+        let before = &document.text[..offset];
+        let word = before.trim_end_matches(|chr: char| chr.is_alphanumeric() || chr == '_');
+        if !word.ends_with('@') {
+            return json!([]);
+        }
+        let analysis = Analysis::analyze(uri, &document.text);
+        let mut items: Vec<Value> = document
+            .functions
+            .iter()
+            .map(|function| completion_item(&function.name, &signature(&function.name, &function.prototype)))
+            .collect();
+        for (name, prototype) in &analysis.natives {
+            if document.functions.iter().any(|function| function.name == *name) {
+                continue;
+            }
+            items.push(completion_item(name, &signature(name, prototype)));
+        }
+        Value::Array(items)
+    }
+
+    fn document_symbols(&self, params: &Value) -> Value {
+        let Some((uri, document)) = self.document(params) else { return Value::Null };
+        let index = LineIndex::new(&document.text);
+        let analysis = Analysis::analyze(uri, &document.text);
+        let symbols: Vec<Value> = analysis
+            .functions
+            .iter()
+            .map(|function| {
+                json!({
+                    "name": function.name,
+                    "detail": signature(&function.name, &function.prototype),
+                    "kind": SYMBOL_KIND_FUNCTION,
+                    "range": index.range(function.extent.start, function.extent.end),
+                    "selectionRange": index.range(function.span.start, function.span.end),
+                })
+            })
+            .collect();
+        Value::Array(symbols)
+    }
+
+    fn document(&self, params: &Value) -> Option<(&str, &Document)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        self.documents.get_key_value(uri).map(|(uri, document)| (uri.as_str(), document))
+    }
+}
+
+fn completion_item(name: &str, detail: &str) -> Value {
+    json!({ "label": name, "kind": COMPLETION_KIND_FUNCTION, "detail": detail })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::serve;
+    use super::transport::{read_message, write_message};
+    use serde_json::{json, Value};
+
+    const URI: &str = "file:///test.bug";
+    const SOURCE: &str = "fn add(int a, int b) int -> a b +;\nfn main() void -> 1 2 @add @write;\n";
+
+    /// Sends `messages` to a server over in-memory streams and returns everything it wrote back
+    fn session(messages: &[Value]) -> Vec<Value> {
+        let mut input: Vec<u8> = vec![];
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output: Vec<u8> = vec![];
+        serve(input.as_slice(), &mut output).unwrap();
+        let mut reader = output.as_slice();
+        let mut replies = vec![];
+        while let Some(reply) = read_message(&mut reader).unwrap() {
+            replies.push(reply);
+        }
+        replies
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn open(text: &str) -> Value {
+        notification("textDocument/didOpen", json!({ "textDocument": { "uri": URI, "text": text } }))
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({ "textDocument": { "uri": URI }, "position": { "line": line, "character": character } })
+    }
+
+    fn result(replies: &[Value], id: u64) -> Value {
+        let reply = replies.iter().find(|reply| reply["id"] == id).expect("no response");
+        reply["result"].clone()
+    }
+
+    fn diagnostics(replies: &[Value]) -> Vec<Value> {
+        replies
+            .iter()
+            .filter(|reply| reply["method"] == "textDocument/publishDiagnostics")
+            .map(|reply| reply["params"]["diagnostics"].clone())
+            .collect()
+    }
+
+    #[test]
+    fn initialize_advertises_capabilities() {
+        let replies = session(&[request(1, "initialize", json!({})), notification("exit", json!(null))]);
+        let capabilities = &result(&replies, 1)["capabilities"];
+        assert_eq!(capabilities["hoverProvider"], true);
+        assert_eq!(capabilities["definitionProvider"], true);
+        assert_eq!(capabilities["documentSymbolProvider"], true);
+        assert_eq!(capabilities["completionProvider"]["triggerCharacters"], json!(["@"]));
+    }
+
+    #[test]
+    fn publishes_diagnostics_on_open_and_change() {
+        let change = json!({ "textDocument": { "uri": URI }, "contentChanges": [{ "text": SOURCE }] });
+        let replies = session(&[open("fn main() void -> x;"), notification("textDocument/didChange", change)]);
+        let published = diagnostics(&replies);
+        assert_eq!(published.len(), 2);
+        assert_eq!(published[0][0]["message"], "Name `x` is not declared");
+        assert_eq!(published[0][0]["range"]["start"], json!({ "line": 0, "character": 18 }));
+        assert_eq!(published[1], json!([]));
+    }
+
+    #[test]
+    fn hover_shows_the_signature_and_stack() {
+        let replies = session(&[open(SOURCE), request(1, "textDocument/hover", at(1, 24))]);
+        let contents = result(&replies, 1)["contents"]["value"].as_str().unwrap().to_string();
+        assert!(contents.contains("fn add(int, int) int"), "{}", contents);
+        assert!(contents.contains("Stack: `int`"), "{}", contents);
+    }
+
+    #[test]
+    fn definition_jumps_to_the_function() {
+        let replies = session(&[open(SOURCE), request(1, "textDocument/definition", at(1, 24))]);
+        let definition = result(&replies, 1);
+        assert_eq!(definition["uri"], URI);
+        assert_eq!(definition["range"]["start"], json!({ "line": 0, "character": 3 }));
+        assert_eq!(definition["range"]["end"], json!({ "line": 0, "character": 6 }));
+    }
+
+    #[test]
+    fn definition_follows_functions_shadowing_natives() {
+        let text =
+            "fn main() void -> 1 2 @max @write;\nfn max(int l, int r) int -> l;\nfn last() void -> 1 2 @max @write;\n";
+        let replies = session(&[
+            open(text),
+            request(1, "textDocument/definition", at(0, 23)),
+            request(2, "textDocument/definition", at(2, 24)),
+        ]);
+        assert_eq!(result(&replies, 1), Value::Null);
+        assert_eq!(result(&replies, 2)["range"]["start"], json!({ "line": 1, "character": 3 }));
+    }
+
+    #[test]
+    fn completion_lists_functions_and_natives_after_at() {
+        let replies = session(&[
+            open(SOURCE),
+            request(1, "textDocument/completion", at(1, 23)),
+            request(2, "textDocument/completion", at(1, 20)),
+        ]);
+        let items = result(&replies, 1);
+        let labels: Vec<&str> = items.as_array().unwrap().iter().map(|item| item["label"].as_str().unwrap()).collect();
+        assert!(labels.contains(&"add"));
+        assert!(labels.contains(&"write"));
+        assert_eq!(result(&replies, 2), json!([]));
+    }
+
+    #[test]
+    fn document_symbols_list_the_functions() {
+        let params = json!({ "textDocument": { "uri": URI } });
+        let replies = session(&[open(SOURCE), request(1, "textDocument/documentSymbol", params)]);
+        let symbols = result(&replies, 1);
+        let names: Vec<&str> =
+            symbols.as_array().unwrap().iter().map(|symbol| symbol["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["add", "main"]);
+        assert_eq!(symbols[0]["detail"], "fn add(int, int) int");
+    }
+
+    #[test]
+    fn unknown_requests_get_an_error() {
+        let replies = session(&[request(1, "textDocument/rename", json!({}))]);
+        assert_eq!(replies[0]["error"]["code"], super::METHOD_NOT_FOUND);
+    }
+}
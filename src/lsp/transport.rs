@@ -0,0 +1,37 @@
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+
+/// Reads one `Content-Length` framed JSON-RPC message, `None` means the client closed the stream
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = Some(value.trim().parse().map_err(|_| invalid_data("Invalid `Content-Length`"))?);
+            }
+        }
+    }
+    let length = content_length.ok_or_else(|| invalid_data("Missing `Content-Length` header"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let message = serde_json::from_slice(&body).map_err(|err| invalid_data(&err.to_string()))?;
+    Ok(Some(message))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
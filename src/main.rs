@@ -1,12 +1,26 @@
 mod bugc;
 mod bvm;
 mod cli;
+mod dap;
+mod debugger;
+mod lsp;
+mod profiler;
+mod repl;
+mod tracer;
 mod utils;
 
-use bug::stdlib::list_natives;
-use bugc::{compile, utils::get_file_stem};
-use bvm::{engine::Engine, load_program_binary};
+use bug::assembler::assemble;
+use bug::disassembler::{disassemble, disassemble_json};
+use bug::stdlib::{NativeFn, NativesBuilder, StdlibOptions};
+use bug::vm::verifier::verify;
+use bug::vm::{Engine, Limits};
+use bug::Program;
+use bugc::utils::{get_file_stem, read_file};
+use bugc::{compile, format};
+use bvm::{exit_on_runtime_error, exit_with_result, load_program_binary, read_program_binary};
+use std::collections::HashMap;
 use std::io::Write;
+use tracer::TraceOptions;
 
 fn main() {
     let matches = cli::command_line();
@@ -15,26 +29,134 @@ fn main() {
         Some(("run", matches)) => {
             let file_path = matches.get_one::<String>("file").unwrap();
             let program = compile(file_path);
-            Engine::bootstrap(program, list_natives()).run();
+            // Read again for stack traces, which only show excerpts of a source known to match the program
+            let source = read_file(file_path).ok();
+            if matches.get_flag("trace") {
+                let natives = natives(&program, file_path, matches);
+                tracer::run(program, natives, args(matches), &trace_options(matches), limits(matches), source);
+            } else {
+                run_program(program, file_path, source, matches);
+            }
         }
         Some(("run-bin", matches)) => {
             let file_path = matches.get_one::<String>("file").unwrap();
             let program = load_program_binary(file_path);
-            Engine::bootstrap(program, list_natives()).run();
+            let source = matches.get_one::<String>("source").map(|source_path| {
+                read_file(source_path).unwrap_or_else(|err| {
+                    eprintln!("[Error]: Couldn't read file {} {}", source_path, err);
+                    std::process::exit(1);
+                })
+            });
+            run_program(program, file_path, source, matches);
         }
         Some(("compile", matches)) => {
             let file_path = matches.get_one::<String>("file").unwrap();
             let program = compile(file_path);
-            let program_binary = bincode::serialize(&program).unwrap_or_else(|err| {
-                eprintln!("[ERROR]: Couldn't serialize your program: {}", err.to_string());
+            save_program_binary(&program, file_path);
+        }
+        Some(("asm", matches)) => {
+            let file_path = matches.get_one::<String>("file").unwrap();
+            let source = read_file(file_path).unwrap_or_else(|err| {
+                eprintln!("[Error]: Couldn't read file {} {}", file_path, err);
                 std::process::exit(1);
             });
-            let mut out_file = std::fs::File::create(&format!("{}.bin", get_file_stem(file_path))).unwrap();
-            out_file.write(&program_binary).unwrap_or_else(|err| {
-                eprintln!("[ERROR] Couldn't save serialized program into file: {}", err);
+            let program = assemble(&source).unwrap_or_else(|err| {
+                eprintln!("[Error]: {}:{}: {}", file_path, err.line, err.message);
                 std::process::exit(1);
             });
+            save_program_binary(&program, file_path);
+        }
+        Some(("disasm", matches)) => {
+            let file_path = matches.get_one::<String>("file").unwrap();
+            // Broken programs should still be inspectable
+            let program = read_program_binary(file_path);
+            if matches.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&disassemble_json(&program)).unwrap());
+            } else {
+                print!("{}", disassemble(&program));
+            }
+        }
+        Some(("fmt", matches)) => {
+            let check = matches.get_flag("check");
+            let mut formatted = true;
+            for file_path in matches.get_many::<String>("files").unwrap() {
+                formatted &= format(file_path, check);
+            }
+            if !formatted {
+                std::process::exit(1);
+            }
         }
+        Some(("debug", matches)) => debugger::run(matches.get_one::<String>("file").unwrap()),
+        Some(("repl", _)) => repl::run(),
+        Some(("dap", _)) => dap::run(),
+        Some(("lsp", _)) => lsp::run(),
         _ => panic!("No valid command was provided."),
     }
 }
+
+fn run_program(program: Program, file_path: &str, source: Option<String>, matches: &clap::ArgMatches) {
+    let natives = natives(&program, file_path, matches);
+    if matches.get_flag("profile") {
+        profiler::run(program, natives, args(matches), file_path, limits(matches), source);
+    } else {
+        let mut engine = Engine::bootstrap(program, natives).with_args(args(matches)).with_limits(limits(matches));
+        if let Some(source) = source {
+            engine = engine.with_source(source);
+        }
+        match engine.run() {
+            Ok(result) => exit_with_result(result.as_ref()),
+            Err(err) => exit_on_runtime_error(&engine, err),
+        }
+    }
+}
+
+/// Arguments after `--`, passed to `main`
+fn args(matches: &clap::ArgMatches) -> Vec<String> {
+    matches.get_many::<String>("args").unwrap_or_default().cloned().collect()
+}
+
+/// The natives selected on the command line, exits when `program` calls another one, e.g. a file native with
+/// `--no-files`
+fn natives(program: &Program, file_path: &str, matches: &clap::ArgMatches) -> HashMap<String, NativeFn> {
+    let options = StdlibOptions {
+        seed: matches.get_one::<u64>("seed").copied(),
+        fake_time: matches.get_one::<i32>("fake-time").copied(),
+        no_files: matches.get_flag("no-files"),
+    };
+    let natives = NativesBuilder::stdlib_with(&options).build();
+    if let Err(err) = verify(program, &natives) {
+        eprintln!("[Error]: {} is not a valid program: {}", file_path, err);
+        std::process::exit(1);
+    }
+    natives
+}
+
+fn limits(matches: &clap::ArgMatches) -> Limits {
+    Limits {
+        max_steps: matches.get_one::<usize>("max-steps").map(|steps| *steps as u64),
+        max_call_depth: matches.get_one::<usize>("max-call-depth").copied(),
+        max_stack: matches.get_one::<usize>("max-stack").copied(),
+        max_heap_bytes: matches.get_one::<usize>("max-heap").copied(),
+    }
+}
+
+fn trace_options(matches: &clap::ArgMatches) -> TraceOptions {
+    TraceOptions {
+        functions: matches.get_many::<String>("trace-function").unwrap_or_default().cloned().collect(),
+        limit: matches.get_one::<usize>("trace-limit").copied(),
+        json: matches.get_flag("trace-json"),
+    }
+}
+
+/// Writes `program` next to the working directory as `<stem of file_path>.bin`
+fn save_program_binary(program: &Program, file_path: &str) {
+    let program_binary = bug::binary::encode(program).unwrap_or_else(|err| {
+        eprintln!("[ERROR]: Couldn't serialize your program: {}", err);
+        std::process::exit(1);
+    });
+    let mut out_file = std::fs::File::create(format!("{}.bin", get_file_stem(file_path))).unwrap();
+    out_file.write_all(&program_binary).unwrap_or_else(|err| {
+        eprintln!("[ERROR] Couldn't save serialized program into file: {}", err);
+        std::process::exit(1);
+    });
+}
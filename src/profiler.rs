@@ -0,0 +1,294 @@
+use crate::bugc::utils::get_file_stem;
+use crate::bvm::{exit_on_runtime_error, exit_with_result};
+use bug::bytecode::Opcode;
+use bug::disassembler::instruction;
+use bug::stdlib::NativeFn;
+use bug::vm::{Engine, Limits, RuntimeError};
+use bug::Program;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Instructions listed in the report, the folded stacks have all of them
+const HOTTEST_INSTRUCTIONS: usize = 10;
+
+#[derive(Default)]
+struct FunctionProfile {
+    calls: u64,
+    instructions: u64,
+    /// Time spent executing the function's own instructions, or inside of it for natives
+    exclusive: Duration,
+    /// Time between entering and leaving the function, callees included
+    inclusive: Duration,
+    /// Executions of every instruction, indexed by offset
+    counts: HashMap<usize, u64>,
+    /// Activations currently on the call stack, only the outermost one counts towards `inclusive`
+    active: usize,
+}
+
+/// A function of the program or a native, by index, since a function can shadow a native of the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Callee {
+    Function(usize),
+    Native(usize),
+}
+
+/// A call on the profiler's shadow of the engine's call stack
+struct Call {
+    callee: Callee,
+    entered: Instant,
+}
+
+#[derive(Default)]
+struct Profiler {
+    functions: HashMap<Callee, FunctionProfile>,
+    calls: Vec<Call>,
+    /// Instructions executed by each call stack, outermost call first
+    folded: HashMap<Vec<Callee>, u64>,
+    instructions: u64,
+}
+
+/// Runs `program` while counting what it executes, then reports on stderr and writes `<stem>.folded`
+pub fn run(
+    program: Program,
+    natives: HashMap<String, NativeFn>,
+    args: Vec<String>,
+    file_path: &str,
+    limits: Limits,
+    source: Option<String>,
+) {
+    // The report lists instructions, the engine takes ownership of the program and the natives
+    let listing = program.clone();
+    let mut engine = Engine::bootstrap(program, natives).with_args(args).with_limits(limits);
+    if let Some(source) = source {
+        engine = engine.with_source(source);
+    }
+    let started = Instant::now();
+    if let Err(err) = engine.start() {
+        exit_on_runtime_error(&engine, err);
+    }
+    let (profiler, error) = profile(&mut engine, &listing, started);
+
+    eprint!("{}", profiler.report(file_path, started.elapsed(), &listing));
+    let folded_path = format!("{}.folded", get_file_stem(file_path));
+    match std::fs::File::create(&folded_path).and_then(|mut file| file.write_all(profiler.folded(&listing).as_bytes()))
+    {
+        Ok(()) => eprintln!("Folded stacks written to {}", folded_path),
+        Err(err) => eprintln!("[Error]: Couldn't write {}: {}", folded_path, err),
+    }
+    if let Some(err) = error {
+        exit_on_runtime_error(&engine, err);
+    }
+    exit_with_result(engine.result());
+}
+
+/// Steps the started `engine` to the end while counting, the profile still covers what ran before a runtime error
+fn profile(engine: &mut Engine, program: &Program, started: Instant) -> (Profiler, Option<RuntimeError>) {
+    let mut profiler = Profiler::default();
+    // `start` checked that there is a `main`
+    let main = program.function_index("main").unwrap_or_default();
+    profiler.enter(Callee::Function(main), started);
+
+    let mut last = started;
+    loop {
+        let frame = engine.frame();
+        let ip = frame.ip;
+        let callee = match frame.function.code.get_at(ip) {
+            Some(Opcode::INVOKE(index)) => Some(Callee::Function(*index)),
+            Some(Opcode::INVOKE_NATIVE(index)) => Some(Callee::Native(*index)),
+            _ => None,
+        };
+        let depth = engine.depth();
+        let current = profiler.count(ip);
+
+        let running = engine.step();
+        let now = Instant::now();
+        let elapsed = now - last;
+        last = now;
+        match callee {
+            Some(native @ Callee::Native(_)) => profiler.native_call(native, elapsed),
+            _ => profiler.function(current).exclusive += elapsed,
+        }
+        if running != Ok(true) {
+            profiler.leave(now);
+            return (profiler, running.err());
+        }
+        if engine.depth() > depth {
+            profiler.enter(callee.unwrap_or(current), now);
+        } else if engine.depth() < depth {
+            profiler.leave(now);
+        }
+    }
+}
+
+impl Callee {
+    /// Natives are marked, a function of the program may have the same name
+    fn name(self, program: &Program) -> String {
+        match self {
+            Self::Function(index) => program.fns.get(index).map_or_else(|| format!("#{}", index), |f| f.name.clone()),
+            Self::Native(index) => match program.natives.get(index) {
+                Some(name) => format!("{} (native)", name),
+                None => format!("#{} (native)", index),
+            },
+        }
+    }
+}
+
+impl Profiler {
+    fn function(&mut self, callee: Callee) -> &mut FunctionProfile {
+        self.functions.entry(callee).or_default()
+    }
+
+    /// Counts the instruction at `ip` of the current call, and returns who it belongs to
+    fn count(&mut self, ip: usize) -> Callee {
+        self.instructions += 1;
+        let current = self.calls.last().map_or(Callee::Function(0), |call| call.callee);
+        let function = self.function(current);
+        function.instructions += 1;
+        *function.counts.entry(ip).or_default() += 1;
+        let stack: Vec<Callee> = self.calls.iter().map(|call| call.callee).collect();
+        *self.folded.entry(stack).or_default() += 1;
+        current
+    }
+
+    fn enter(&mut self, callee: Callee, now: Instant) {
+        let function = self.function(callee);
+        function.calls += 1;
+        function.active += 1;
+        self.calls.push(Call { callee, entered: now });
+    }
+
+    fn leave(&mut self, now: Instant) {
+        let Some(call) = self.calls.pop() else { return };
+        let function = self.function(call.callee);
+        function.active -= 1;
+        if function.active == 0 {
+            function.inclusive += now - call.entered;
+        }
+    }
+
+    /// Natives run within a single instruction of their caller
+    fn native_call(&mut self, native: Callee, elapsed: Duration) {
+        let function = self.function(native);
+        function.calls += 1;
+        function.exclusive += elapsed;
+        function.inclusive += elapsed;
+    }
+
+    fn report(&self, file_path: &str, total: Duration, program: &Program) -> String {
+        let mut out =
+            format!("\nProfile of {}: {} instructions in {}\n\n", file_path, self.instructions, format_duration(total));
+        let mut functions: Vec<(String, Callee, &FunctionProfile)> =
+            self.functions.iter().map(|(callee, function)| (callee.name(program), *callee, function)).collect();
+        functions.sort_by(|(lhs_name, _, lhs), (rhs_name, _, rhs)| {
+            rhs.exclusive.cmp(&lhs.exclusive).then(lhs_name.cmp(rhs_name))
+        });
+        let width = functions.iter().map(|(name, ..)| name.len()).max().unwrap_or(0).max("function".len());
+        out.push_str(&format!(
+            "{:<width$}  {:>8}  {:>12}  {:>12}  {:>12}\n",
+            "function", "calls", "instructions", "exclusive", "inclusive"
+        ));
+        for (name, _, function) in &functions {
+            out.push_str(&format!(
+                "{:<width$}  {:>8}  {:>12}  {:>12}  {:>12}\n",
+                name,
+                function.calls,
+                function.instructions,
+                format_duration(function.exclusive),
+                format_duration(function.inclusive)
+            ));
+        }
+
+        let mut hottest: Vec<(&String, Callee, usize, u64)> = vec![];
+        for (name, callee, function) in &functions {
+            hottest.extend(function.counts.iter().map(|(offset, count)| (name, *callee, *offset, *count)));
+        }
+        hottest.sort_by(|lhs, rhs| rhs.3.cmp(&lhs.3).then(lhs.0.cmp(rhs.0)).then(lhs.2.cmp(&rhs.2)));
+        out.push_str("\nHottest instructions\n");
+        for (name, callee, offset, count) in hottest.into_iter().take(HOTTEST_INSTRUCTIONS) {
+            let function = match callee {
+                Callee::Function(index) => program.fns.get(index),
+                Callee::Native(_) => None,
+            };
+            let opcode = function.and_then(|function| function.code.get_at(offset));
+            let listing = opcode.map_or(String::new(), |opcode| instruction(opcode, program));
+            out.push_str(&format!("{:>12}  {:<width$}  {:04}  {}\n", count, name, offset, listing));
+        }
+        out
+    }
+
+    /// One `stack count` line per call stack, the input format of flame graph tools
+    fn folded(&self, program: &Program) -> String {
+        let mut stacks: Vec<(String, u64)> = self
+            .folded
+            .iter()
+            .map(|(stack, count)| {
+                let names: Vec<String> = stack.iter().map(|callee| callee.name(program)).collect();
+                (names.join(";"), *count)
+            })
+            .collect();
+        stacks.sort();
+        stacks.iter().map(|(stack, count)| format!("{} {}\n", stack, count)).collect()
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{profile, Callee, Profiler};
+    use crate::bugc::try_compile;
+    use bug::stdlib::list_natives;
+    use bug::vm::{Engine, RuntimeError};
+    use bug::Program;
+    use std::time::{Duration, Instant};
+
+    /// `first` calls the native `max`, `main` the function shadowing it
+    const SOURCE: &str =
+        "fn first() int -> 7 9 @max;\nfn max(int l, int r) int -> l;\nfn main() int -> @first 2 @max;\n";
+
+    fn profiled(source: &str) -> (Program, Profiler, Option<RuntimeError>) {
+        let program = try_compile("profiled.bug", source).unwrap();
+        let mut engine = Engine::bootstrap(program.clone(), list_natives());
+        engine.start().unwrap();
+        let (profiler, error) = profile(&mut engine, &program, Instant::now());
+        (program, profiler, error)
+    }
+
+    /// Calls and instructions of `callee`
+    fn counts(profiler: &Profiler, callee: Callee) -> (u64, u64) {
+        let function = &profiler.functions[&callee];
+        (function.calls, function.instructions)
+    }
+
+    #[test]
+    fn counts_functions_and_natives_apart() {
+        let (program, profiler, error) = profiled(SOURCE);
+        assert_eq!(error, None);
+        assert_eq!(profiler.instructions, 10);
+        assert_eq!(program.natives, ["max"]);
+        assert_eq!(counts(&profiler, Callee::Function(2)), (1, 4));
+        assert_eq!(counts(&profiler, Callee::Function(0)), (1, 4));
+        assert_eq!(counts(&profiler, Callee::Function(1)), (1, 2));
+        assert_eq!(counts(&profiler, Callee::Native(0)), (1, 0));
+        let report = profiler.report("profiled.bug", Duration::ZERO, &program);
+        assert!(report.contains("\nmax (native)         1             0"), "{}", report);
+        assert!(report.contains("\nmax                  1             2"), "{}", report);
+    }
+
+    #[test]
+    fn folds_instructions_by_call_stack() {
+        let (program, profiler, _) = profiled(SOURCE);
+        assert_eq!(profiler.folded(&program), "main 4\nmain;first 4\nmain;max 2\n");
+    }
+
+    #[test]
+    fn keeps_what_ran_before_a_runtime_error() {
+        let (program, profiler, error) = profiled("fn main() int -> 2147483647 1 +;\n");
+        assert_eq!(error, Some(RuntimeError::IntegerOverflow { lhs: i32::MAX, rhs: 1 }));
+        assert_eq!(profiler.folded(&program), "main 3\n");
+        assert!(profiler.calls.is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::bugc::ast::Statement;
+use crate::bugc::checker::Checker;
+use crate::bugc::codegenerator::CodeGenerator;
+use crate::bugc::lexer::Lexer;
+use crate::bugc::parser::Parser;
+use crate::bugc::token::TokenKind;
+use bug::disassembler::disassemble_function;
+use bug::stdlib::{list_natives, prototypes};
+use bug::vm::Engine;
+use bug::{FunctionPrototype, Object, Type};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+const FILE_NAME: &str = "<repl>";
+const PROMPT: &str = "bug> ";
+const CONTINUATION_PROMPT: &str = "...> ";
+
+const HELP: &str = "\
+Enter function definitions or postfix expressions, the stack is shown after each expression.
+A definition spans lines until its closing `;`.
+
+  :type <expr>   show the types the expression leaves on the stack
+  :dis <name>    show the bytecode of a function
+  :help          show this message
+  :quit          leave the repl";
+
+/// Definitions accumulated so far and the engine that runs them
+struct Repl {
+    generator: CodeGenerator,
+    engine: Engine,
+    prototypes: HashMap<String, FunctionPrototype>,
+}
+
+pub fn run() {
+    let mut repl = Repl::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut input = String::new();
+    println!("Bug {} interactive mode, type `:help` for help", env!("CARGO_PKG_VERSION"));
+    loop {
+        print!("{}", if input.is_empty() { PROMPT } else { CONTINUATION_PROMPT });
+        io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(err)) => {
+                eprintln!("[Error]: Couldn't read input: {}", err);
+                std::process::exit(1);
+            }
+            None => break,
+        };
+        input.push_str(&line);
+        input.push('\n');
+        if !is_complete(&input) {
+            continue;
+        }
+        let entry = std::mem::take(&mut input);
+        let entry = entry.trim();
+        match entry.split_once(char::is_whitespace).unwrap_or((entry, "")) {
+            ("", _) => {}
+            (":quit" | ":q", _) => break,
+            (":help" | ":h", _) => println!("{}", HELP),
+            (":type" | ":t", expression) => repl.show_type(&format!("{}\n", expression)),
+            (":dis" | ":d", name) => repl.disassemble(name.trim()),
+            (command, _) if command.starts_with(':') => eprintln!("Unknown command `{}`, try `:help`", command),
+            // The highlighter of diagnostics wants lines to end with a newline
+            _ => repl.evaluate(&format!("{}\n", entry)),
+        }
+    }
+}
+
+/// A definition is complete once its closing `;` was entered, anything else ends with the line
+fn is_complete(input: &str) -> bool {
+    let mut lexer = Lexer::new(input);
+    let mut first: Option<TokenKind> = None;
+    let mut last: Option<TokenKind> = None;
+    loop {
+        match lexer.next_token() {
+            Ok(token) if token.kind == TokenKind::Eof => break,
+            Ok(token) => {
+                first.get_or_insert(token.kind.clone());
+                last = Some(token.kind);
+            }
+            // Let the parser report it
+            Err(_) => return true,
+        }
+    }
+    first != Some(TokenKind::Function) || last == Some(TokenKind::Semicolon)
+}
+
+impl Repl {
+    fn new() -> Self {
+        let generator = CodeGenerator::setup(FILE_NAME);
+        let engine = Engine::bootstrap(generator.program().clone(), list_natives());
+        Self { generator, engine, prototypes: HashMap::new() }
+    }
+
+    fn evaluate(&mut self, input: &str) {
+        let Some(ast) = parse(input) else { return };
+        let (mut functions, expressions): (Vec<Statement>, Vec<Statement>) =
+            ast.into_iter().partition(|statement| matches!(statement, Statement::Function(_)));
+        if !functions.is_empty() && !self.define(input, &mut functions) {
+            return;
+        }
+        if !expressions.is_empty() {
+            self.execute(input, expressions);
+        }
+    }
+
+    fn define(&mut self, input: &str, functions: &mut Vec<Statement>) -> bool {
+        let mut checker = self.checker(input);
+        if let Some(diagnostics) = checker.check(functions) {
+            eprint!("{}", diagnostics);
+            return false;
+        }
+        for statement in functions.iter() {
+            if let Statement::Function(function) = statement {
+                let parameters_types: Vec<Type> =
+                    function.parameters.parameters.iter().map(|p| p.typ.clone()).collect();
+                let prototype =
+                    FunctionPrototype::new(parameters_types.len(), function.return_type.clone(), parameters_types);
+                self.prototypes.insert(function.identifier.label.clone(), prototype);
+                println!("defined `{}`", function.identifier.label);
+            }
+        }
+        let program = self.generator.emit(std::mem::take(functions));
+        self.engine.load(program);
+        true
+    }
+
+    fn execute(&mut self, input: &str, mut expressions: Vec<Statement>) {
+        let mut checker = self.checker(input);
+        if let Err(diagnostics) = checker.check_block(&mut expressions) {
+            eprint!("{}", diagnostics);
+            return;
+        }
+        let function = self.generator.emit_block(expressions);
+        self.engine.load(self.generator.program().clone());
+        match self.engine.eval(FILE_NAME, function) {
+            Ok(stack) => println!("{}", format_stack(&stack)),
+            Err(err) => {
+                eprintln!("RUNTIME EXCEPTION: {}", err);
+                eprint!("{}", self.engine.stack_trace());
+            }
+        }
+    }
+
+    fn show_type(&mut self, input: &str) {
+        let Some(mut expressions) = parse(input) else { return };
+        let mut checker = self.checker(input);
+        match checker.check_block(&mut expressions) {
+            Ok(types) if types.is_empty() => println!("(empty)"),
+            Ok(types) => println!("{}", types.iter().map(|typ| typ.to_string()).collect::<Vec<String>>().join(" ")),
+            Err(diagnostics) => eprint!("{}", diagnostics),
+        }
+    }
+
+    fn disassemble(&self, name: &str) {
+        let program = self.generator.program();
+        if let Some(function) = program.function(name) {
+            print!("{}", disassemble_function(function, program));
+        } else if list_natives().contains_key(name) {
+            println!("`{}` is a native function", name);
+        } else {
+            eprintln!("No function named `{}`", name);
+        }
+    }
+
+    fn checker<'a>(&self, input: &'a str) -> Checker<'a> {
+        let mut checker = Checker::new(FILE_NAME, input, prototypes(&list_natives()));
+        for (name, prototype) in &self.prototypes {
+            checker.declare_function(name.clone(), prototype.clone());
+        }
+        checker
+    }
+}
+
+fn parse(input: &str) -> Option<Vec<Statement>> {
+    let mut lexer = Lexer::new(input);
+    match Parser::new(FILE_NAME, input, &mut lexer).parse() {
+        Ok(ast) => Some(ast),
+        Err(err) => {
+            eprint!("{}", err);
+            None
+        }
+    }
+}
+
+pub fn format_stack(stack: &[Object]) -> String {
+    if stack.is_empty() {
+        return "(empty)".to_string();
+    }
+    let values: Vec<String> = stack
+        .iter()
+        .map(|object| match object {
+            Object::String(string) => format!("{:?}", string),
+            object => object.to_string(),
+        })
+        .collect();
+    values.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_complete, Repl};
+
+    #[test]
+    fn expressions_are_complete_at_the_end_of_the_line() {
+        assert!(is_complete("1 2 +"));
+        assert!(is_complete("1 2 -"));
+        assert!(is_complete("-"));
+        assert!(is_complete("\"unterminated"));
+    }
+
+    #[test]
+    fn definitions_are_complete_at_their_semicolon() {
+        assert!(!is_complete("fn f() int ->"));
+        assert!(!is_complete("fn f() int -> 1\n2 -"));
+        assert!(is_complete("fn f() int ->\n1;"));
+    }
+
+    #[test]
+    fn rejected_operands_keep_the_session_alive() {
+        let mut repl = Repl::new();
+        repl.evaluate("\"a\" \"b\" +\n");
+        repl.evaluate("fn f() int -> 1 2 -;\n");
+        repl.evaluate("fn g() int -> 1 2 +;\n");
+        assert!(!repl.prototypes.contains_key("f"));
+        assert!(repl.prototypes.contains_key("g"));
+    }
+}
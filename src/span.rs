@@ -1,4 +1,6 @@
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Span {
     pub line: usize,
     pub column: usize,
@@ -11,9 +13,3 @@ impl Span {
         Self { line, column, start, end }
     }
 }
-
-impl Default for Span {
-    fn default() -> Self {
-        Self { line: 0, column: 0, start: 0, end: 0 }
-    }
-}
@@ -0,0 +1,21 @@
+use super::{str_arg, NativeContext, NativeError, NativeResult, NativesBuilder};
+use crate::{Object, Type};
+use std::env;
+
+/// Environment variables of the process running the program
+pub fn register(natives: NativesBuilder) -> NativesBuilder {
+    natives.with("env", vec![Type::String], Type::String, get).with("has_env", vec![Type::String], Type::Boolean, has)
+}
+
+fn get(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let name = str_arg(&args, 0)?;
+    match env::var(name) {
+        Ok(value) => Ok(Some(Object::String(value))),
+        Err(err) => Err(NativeError::new(format!("Couldn't read the environment variable {}: {}", name, err))),
+    }
+}
+
+/// Whether `env` would succeed
+fn has(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::Boolean(env::var(str_arg(&args, 0)?).is_ok())))
+}
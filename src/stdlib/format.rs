@@ -0,0 +1,222 @@
+use super::{NativeContext, NativeError, NativeResult, NativesBuilder};
+use crate::{Object, Type};
+use std::fmt;
+
+/// Widths above this are mistakes, and would let a template allocate without bound
+const MAX_WIDTH: usize = 1024;
+
+/// Natives filling the placeholders of a template, their last argument, with the values pushed before it
+pub fn register(natives: NativesBuilder) -> NativesBuilder {
+    natives.with_template("format", Type::String, format).with_template("printf", Type::Void, printf)
+}
+
+/// A `{}` of a template: `{:5}` aligns its value right in 5 characters, `{:<5}` left and `{:05}` pads an `int`
+/// with zeros
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Placeholder {
+    pub width: usize,
+    pub left: bool,
+    pub zeros: bool,
+}
+
+impl Placeholder {
+    /// Whether a value of type `typ` can fill the placeholder, lists have no textual form of their own
+    pub fn accepts(&self, typ: &Type) -> bool {
+        match typ {
+            Type::Integer => true,
+            Type::String | Type::Boolean => !self.zeros,
+            Type::Void | Type::List(_) => false,
+        }
+    }
+
+    fn fill(&self, value: &Object) -> Result<String, NativeError> {
+        let text = match value {
+            Object::Integer(integer) if self.zeros => return Ok(format!("{:0width$}", integer, width = self.width)),
+            _ if self.accepts(&value.get_type()) => value.to_string(),
+            _ => return Err(NativeError::new(format!("Can't fill `{}` with a `{}`", self, value.get_type()))),
+        };
+        match self.left {
+            true => Ok(format!("{:<width$}", text, width = self.width)),
+            false => Ok(format!("{:>width$}", text, width = self.width)),
+        }
+    }
+}
+
+impl fmt::Display for Placeholder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.width, self.left, self.zeros) {
+            (0, _, _) => write!(f, "{{}}"),
+            (width, true, _) => write!(f, "{{:<{}}}", width),
+            (width, _, true) => write!(f, "{{:0{}}}", width),
+            (width, _, _) => write!(f, "{{:{}}}", width),
+        }
+    }
+}
+
+enum Piece {
+    Text(String),
+    Value(Placeholder),
+}
+
+/// The placeholders of `template` in order, a value is taken for each. `{{` and `}}` are literal braces
+pub fn placeholders(template: &str) -> Result<Vec<Placeholder>, String> {
+    let pieces = parse(template)?;
+    Ok(pieces
+        .into_iter()
+        .filter_map(|piece| match piece {
+            Piece::Value(placeholder) => Some(placeholder),
+            Piece::Text(_) => None,
+        })
+        .collect())
+}
+
+fn parse(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek() == Some(&c) => {
+                chars.next();
+                text.push(c);
+            }
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err("A `{` is never closed, write `{{` for a literal brace".to_string()),
+                    }
+                }
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+                pieces.push(Piece::Value(placeholder(&spec)?));
+            }
+            '}' => return Err("A `}` was never opened, write `}}` for a literal brace".to_string()),
+            c => text.push(c),
+        }
+    }
+    pieces.push(Piece::Text(text));
+    Ok(pieces)
+}
+
+fn placeholder(spec: &str) -> Result<Placeholder, String> {
+    if spec.is_empty() {
+        return Ok(Placeholder::default());
+    }
+    let invalid = || format!("Invalid placeholder `{{{}}}`, expecting `{{}}`, `{{:N}}`, `{{:<N}}` or `{{:0N}}`", spec);
+    let options = spec.strip_prefix(':').ok_or_else(invalid)?;
+    let (left, options) = options.strip_prefix('<').map_or((false, options), |width| (true, width));
+    let (zeros, width) = match options.strip_prefix('0') {
+        Some(width) if !left => (true, width),
+        _ => (false, options),
+    };
+    if width.is_empty() || !width.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    match width.parse::<usize>() {
+        Ok(width) if width <= MAX_WIDTH => Ok(Placeholder { width, left, zeros }),
+        _ => Err(format!("The width of `{{{}}}` is larger than {}", spec, MAX_WIDTH)),
+    }
+}
+
+/// Fills the placeholders of `template` with `values`, one each
+pub fn render(template: &str, values: &[Object]) -> Result<String, NativeError> {
+    let pieces = parse(template).map_err(NativeError::new)?;
+    let placeholders = pieces.iter().filter(|piece| matches!(piece, Piece::Value(_))).count();
+    if placeholders != values.len() {
+        return Err(NativeError::new(format!(
+            "The template has {} placeholders but got {} values",
+            placeholders,
+            values.len()
+        )));
+    }
+    let mut values = values.iter();
+    let mut rendered = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Text(text) => rendered.push_str(&text),
+            Piece::Value(placeholder) => rendered.push_str(&placeholder.fill(values.next().unwrap())?),
+        }
+    }
+    Ok(rendered)
+}
+
+/// Splits the arguments of a template native into the template and the values filling it
+fn template_args(args: &[Object]) -> Result<(&str, &[Object]), NativeError> {
+    match args.split_last() {
+        Some((Object::String(template), values)) => Ok((template, values)),
+        _ => Err(NativeError::new("Expecting the template as the last argument")),
+    }
+}
+
+fn format(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (template, values) = template_args(&args)?;
+    Ok(Some(Object::String(render(template, values)?)))
+}
+
+/// Like `print`, writes without a line break and flushes
+fn printf(context: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (template, values) = template_args(&args)?;
+    let rendered = render(template, values)?;
+    let written = write!(context.output, "{}", rendered).and_then(|_| context.output.flush());
+    written.map_err(|err| NativeError::new(format!("Couldn't write: {}", err)))?;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{placeholders, render, Placeholder};
+    use crate::stdlib::NativeError;
+    use crate::Object;
+
+    fn string(text: &str) -> Object {
+        Object::String(text.to_string())
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(placeholders("{{}} {{x}}"), Ok(vec![]));
+        assert_eq!(render("{{{}}}", &[Object::Integer(1)]), Ok("{1}".to_string()));
+    }
+
+    #[test]
+    fn placeholders_parse_their_width() {
+        let expected = vec![
+            Placeholder::default(),
+            Placeholder { width: 5, left: false, zeros: false },
+            Placeholder { width: 3, left: true, zeros: false },
+            Placeholder { width: 7, left: false, zeros: true },
+        ];
+        assert_eq!(placeholders("{} {:5} {:<3} {:07}"), Ok(expected));
+    }
+
+    #[test]
+    fn widths_align_and_pad() {
+        let values = [Object::Integer(42), string("ab"), Object::Integer(-7), Object::Boolean(true)];
+        assert_eq!(render("[{:4}|{:<4}|{:04}|{}]", &values), Ok("[  42|ab  |-007|true]".to_string()));
+    }
+
+    #[test]
+    fn unbalanced_braces_are_rejected() {
+        assert_eq!(placeholders("x {"), Err("A `{` is never closed, write `{{` for a literal brace".to_string()));
+        assert_eq!(placeholders("x {:5"), Err("A `{` is never closed, write `{{` for a literal brace".to_string()));
+        assert_eq!(placeholders("x }"), Err("A `}` was never opened, write `}}` for a literal brace".to_string()));
+    }
+
+    #[test]
+    fn invalid_placeholders_are_rejected() {
+        let invalid = "Invalid placeholder `{x}`, expecting `{}`, `{:N}`, `{:<N}` or `{:0N}`";
+        assert_eq!(placeholders("{x}"), Err(invalid.to_string()));
+        assert!(placeholders("{:}").is_err());
+        assert!(placeholders("{:5x}").is_err());
+        assert_eq!(placeholders("{:1025}"), Err("The width of `{:1025}` is larger than 1024".to_string()));
+    }
+
+    #[test]
+    fn rendering_checks_the_values() {
+        let message = |result: Result<String, NativeError>| result.unwrap_err().message;
+        assert_eq!(message(render("{} {}", &[Object::Integer(1)])), "The template has 2 placeholders but got 1 values");
+        assert_eq!(message(render("{:03}", &[string("ab")])), "Can't fill `{:03}` with a `str`");
+    }
+}
@@ -0,0 +1,107 @@
+use super::{str_arg, NativeContext, NativeError, NativeResult, NativesBuilder};
+use crate::{Object, Type};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Console natives, through the engine's streams, and file natives unless `files` is false
+pub fn register(natives: NativesBuilder, files: bool) -> NativesBuilder {
+    let natives = natives
+        .with_any("write", 1, Type::Void, write)
+        .with_any("print", 1, Type::Void, print)
+        .with_any("write_err", 1, Type::Void, write_err)
+        .with("read_line", vec![], Type::String, read_line);
+    if !files {
+        return natives;
+    }
+    natives
+        .with("read_file", vec![Type::String], Type::String, read_file)
+        .with("write_file", vec![Type::String, Type::String], Type::Void, write_file)
+        .with("append_file", vec![Type::String, Type::String], Type::Void, append_file)
+        .with("file_exists", vec![Type::String], Type::Boolean, file_exists)
+}
+
+fn console_error(err: io::Error) -> NativeError {
+    NativeError::new(format!("Couldn't write: {}", err))
+}
+
+fn file_error(action: &str, path: &str, err: io::Error) -> NativeError {
+    NativeError::new(format!("Couldn't {} {}: {}", action, path, err))
+}
+
+fn write(context: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    for object in args {
+        writeln!(context.output, "{}", object).map_err(console_error)?;
+    }
+    Ok(None)
+}
+
+/// Like `write` without the newline, flushed so that prompts show up before reading
+fn print(context: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    for object in args {
+        write!(context.output, "{}", object).map_err(console_error)?;
+    }
+    context.output.flush().map_err(console_error)?;
+    Ok(None)
+}
+
+fn write_err(context: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    for object in args {
+        writeln!(context.error_output, "{}", object).map_err(console_error)?;
+    }
+    Ok(None)
+}
+
+/// The next line of input without its line break, empty at the end of the input
+fn read_line(context: &mut NativeContext, _: Vec<Object>) -> NativeResult {
+    context.output.flush().map_err(console_error)?;
+    let mut line = String::new();
+    context.input.read_line(&mut line).map_err(|err| NativeError::new(format!("Couldn't read input: {}", err)))?;
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    Ok(Some(Object::String(line.to_string())))
+}
+
+fn read_file(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let path = str_arg(&args, 0)?;
+    let contents = fs::read_to_string(path).map_err(|err| file_error("read", path, err))?;
+    Ok(Some(Object::String(contents)))
+}
+
+/// Creates the file or replaces its contents
+fn write_file(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (path, contents) = (str_arg(&args, 0)?, str_arg(&args, 1)?);
+    fs::write(path, contents).map_err(|err| file_error("write", path, err))?;
+    Ok(None)
+}
+
+/// Creates the file or adds to the end of it
+fn append_file(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (path, contents) = (str_arg(&args, 0)?, str_arg(&args, 1)?);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|err| file_error("append to", path, err))?;
+    Ok(None)
+}
+
+fn file_exists(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::Boolean(Path::new(str_arg(&args, 0)?).is_file())))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stdlib::{NativesBuilder, StdlibOptions};
+
+    #[test]
+    fn file_natives_can_be_left_out() {
+        let natives = NativesBuilder::stdlib_with(&StdlibOptions { no_files: true, ..Default::default() }).build();
+        for name in ["read_file", "write_file", "append_file", "file_exists"] {
+            assert!(!natives.contains_key(name), "`{}` is still there", name);
+        }
+        assert!(natives.contains_key("write") && natives.contains_key("read_line"));
+        assert!(NativesBuilder::stdlib().build().contains_key("read_file"));
+    }
+}
@@ -0,0 +1,130 @@
+use super::{int_arg, NativeContext, NativeError, NativeResult, NativesBuilder};
+use crate::{Object, Type};
+
+/// Integer math natives, overflowing is an error like it is for `+`
+pub fn register(natives: NativesBuilder) -> NativesBuilder {
+    let int = || Type::Integer;
+    natives
+        .with("abs", vec![int()], int(), abs)
+        .with("min", vec![int(), int()], int(), min)
+        .with("max", vec![int(), int()], int(), max)
+        .with("pow", vec![int(), int()], int(), pow)
+        .with("sqrt", vec![int()], int(), sqrt)
+        .with("gcd", vec![int(), int()], int(), gcd)
+        .with("clamp", vec![int(), int(), int()], int(), clamp)
+        .with("sign", vec![int()], int(), sign)
+}
+
+fn overflow(expression: String) -> NativeError {
+    NativeError::new(format!("Integer overflow in {}", expression))
+}
+
+fn abs(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let n = int_arg(&args, 0)?;
+    let result = n.checked_abs().ok_or_else(|| overflow(format!("|{}|", n)))?;
+    Ok(Some(Object::Integer(result)))
+}
+
+fn min(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::Integer(int_arg(&args, 0)?.min(int_arg(&args, 1)?))))
+}
+
+fn max(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::Integer(int_arg(&args, 0)?.max(int_arg(&args, 1)?))))
+}
+
+fn pow(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (base, exponent) = (int_arg(&args, 0)?, int_arg(&args, 1)?);
+    let Ok(exponent) = u32::try_from(exponent) else {
+        return Err(NativeError::new(format!("Negative exponent {}, the result wouldn't be an integer", exponent)));
+    };
+    let result = base.checked_pow(exponent).ok_or_else(|| overflow(format!("{}^{}", base, exponent)))?;
+    Ok(Some(Object::Integer(result)))
+}
+
+/// The largest integer whose square is at most the argument
+fn sqrt(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let n = int_arg(&args, 0)?;
+    if n < 0 {
+        return Err(NativeError::new(format!("Square root of the negative number {}", n)));
+    }
+    let mut root = (n as f64).sqrt() as i64;
+    // The floating point estimate can be off by one for large numbers
+    while root * root > n as i64 {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= n as i64 {
+        root += 1;
+    }
+    Ok(Some(Object::Integer(root as i32)))
+}
+
+/// Greatest common divisor, always positive unless both arguments are zero
+fn gcd(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (lhs, rhs) = (int_arg(&args, 0)?, int_arg(&args, 1)?);
+    let (mut a, mut b) = (lhs.unsigned_abs(), rhs.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    let result = i32::try_from(a).map_err(|_| overflow(format!("gcd({}, {})", lhs, rhs)))?;
+    Ok(Some(Object::Integer(result)))
+}
+
+fn clamp(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (n, low, high) = (int_arg(&args, 0)?, int_arg(&args, 1)?, int_arg(&args, 2)?);
+    if low > high {
+        return Err(NativeError::new(format!("Can't clamp between {} and {}, the bounds are reversed", low, high)));
+    }
+    Ok(Some(Object::Integer(n.clamp(low, high))))
+}
+
+/// -1, 0 or 1
+fn sign(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::Integer(int_arg(&args, 0)?.signum())))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stdlib::{error, run};
+    use crate::Object;
+
+    #[test]
+    fn pow_raises_to_a_power() {
+        assert_eq!(run("pow", (2, 10)), Ok(Some(Object::Integer(1024))));
+        assert_eq!(run("pow", (-3, 3)), Ok(Some(Object::Integer(-27))));
+        assert_eq!(run("pow", (7, 0)), Ok(Some(Object::Integer(1))));
+    }
+
+    #[test]
+    fn pow_rejects_overflow_and_negative_exponents() {
+        assert_eq!(error("pow", (2, 31)), "Integer overflow in 2^31");
+        assert_eq!(error("pow", (2, -1)), "Negative exponent -1, the result wouldn't be an integer");
+    }
+
+    #[test]
+    fn abs_of_the_smallest_integer_overflows() {
+        assert_eq!(run("abs", (-5,)), Ok(Some(Object::Integer(5))));
+        assert_eq!(error("abs", (i32::MIN,)), "Integer overflow in |-2147483648|");
+    }
+
+    #[test]
+    fn sqrt_rounds_down() {
+        assert_eq!(run("sqrt", (15,)), Ok(Some(Object::Integer(3))));
+        assert_eq!(run("sqrt", (16,)), Ok(Some(Object::Integer(4))));
+        assert_eq!(run("sqrt", (i32::MAX,)), Ok(Some(Object::Integer(46340))));
+        assert_eq!(error("sqrt", (-1,)), "Square root of the negative number -1");
+    }
+
+    #[test]
+    fn gcd_is_positive() {
+        assert_eq!(run("gcd", (-12, 18)), Ok(Some(Object::Integer(6))));
+        assert_eq!(run("gcd", (0, 0)), Ok(Some(Object::Integer(0))));
+        assert_eq!(error("gcd", (i32::MIN, 0)), "Integer overflow in gcd(-2147483648, 0)");
+    }
+
+    #[test]
+    fn clamp_rejects_reversed_bounds() {
+        assert_eq!(run("clamp", (15, 0, 10)), Ok(Some(Object::Integer(10))));
+        assert_eq!(error("clamp", (5, 10, 0)), "Can't clamp between 10 and 0, the bounds are reversed");
+    }
+}
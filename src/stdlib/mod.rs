@@ -0,0 +1,230 @@
+mod env;
+pub mod format;
+mod io;
+mod math;
+mod random;
+mod string;
+mod time;
+
+use crate::{FunctionPrototype, Object, Type};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+/// What a native sees of the engine calling it
+pub struct NativeContext<'a> {
+    /// Name of the function that made the call
+    pub caller: &'a str,
+    /// Number of calls below the caller
+    pub depth: usize,
+    /// The engine's output stream, stdout unless the host redirected it
+    pub output: &'a mut dyn Write,
+    /// The engine's error output stream, stderr unless the host redirected it
+    pub error_output: &'a mut dyn Write,
+    /// The engine's input stream, stdin unless the host redirected it
+    pub input: &'a mut dyn BufRead,
+}
+
+/// A native function failed, the engine raises it as a runtime exception
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeError {
+    pub message: String,
+}
+
+impl NativeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into() }
+    }
+}
+
+impl fmt::Display for NativeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for NativeError {}
+
+/// What a native returns, if anything, or why it failed
+pub type NativeResult = Result<Option<Object>, NativeError>;
+
+/// Receives the arguments in the order of the prototype's parameters
+pub type NativeFunction = Box<dyn FnMut(&mut NativeContext, Vec<Object>) -> NativeResult>;
+
+pub struct NativeFn {
+    pub prototype: FunctionPrototype,
+    pub function: NativeFunction,
+}
+
+/// Pins what the standard library would otherwise take from the environment, so that runs are reproducible
+#[derive(Debug, Clone, Default)]
+pub struct StdlibOptions {
+    /// Seed of `random`, taken from the time when `None`
+    pub seed: Option<u64>,
+    /// Seconds since the epoch returned by `time`, also makes `clock` advance one millisecond per call
+    pub fake_time: Option<i32>,
+    /// Leaves out the natives touching the filesystem, for running untrusted programs
+    pub no_files: bool,
+}
+
+/// Collects the natives a program can call, starting empty or from the standard library
+#[derive(Default)]
+pub struct NativesBuilder {
+    natives: HashMap<String, NativeFn>,
+}
+
+impl NativesBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stdlib() -> Self {
+        Self::stdlib_with(&StdlibOptions::default())
+    }
+
+    pub fn stdlib_with(options: &StdlibOptions) -> Self {
+        let natives = format::register(env::register(math::register(string::register(io::register(
+            Self::new(),
+            !options.no_files,
+        )))));
+        time::register(random::register(natives, options.seed), options.fake_time)
+    }
+
+    /// Adds or replaces the native `name` taking parameters of `parameters_types`
+    pub fn with<F>(self, name: &str, parameters_types: Vec<Type>, return_type: Type, function: F) -> Self
+    where
+        F: FnMut(&mut NativeContext, Vec<Object>) -> NativeResult + 'static,
+    {
+        let prototype = FunctionPrototype::new(parameters_types.len(), return_type, parameters_types);
+        self.with_prototype(name, prototype, function)
+    }
+
+    /// Adds or replaces the native `name` taking `arity` parameters of any type
+    pub fn with_any<F>(self, name: &str, arity: usize, return_type: Type, function: F) -> Self
+    where
+        F: FnMut(&mut NativeContext, Vec<Object>) -> NativeResult + 'static,
+    {
+        self.with_prototype(name, FunctionPrototype::new(arity, return_type, vec![]), function)
+    }
+
+    /// Adds or replaces the native `name` taking a template string, see `format::placeholders`, and before it a
+    /// value for each placeholder of the template
+    pub fn with_template<F>(self, name: &str, return_type: Type, function: F) -> Self
+    where
+        F: FnMut(&mut NativeContext, Vec<Object>) -> NativeResult + 'static,
+    {
+        let prototype =
+            FunctionPrototype { template: true, ..FunctionPrototype::new(1, return_type, vec![Type::String]) };
+        self.with_prototype(name, prototype, function)
+    }
+
+    fn with_prototype<F>(mut self, name: &str, prototype: FunctionPrototype, function: F) -> Self
+    where
+        F: FnMut(&mut NativeContext, Vec<Object>) -> NativeResult + 'static,
+    {
+        self.natives.insert(name.to_string(), NativeFn { prototype, function: Box::new(function) });
+        self
+    }
+
+    pub fn build(self) -> HashMap<String, NativeFn> {
+        self.natives
+    }
+}
+
+/// The string argument at `index`, natives can't assume the program was checked
+pub fn str_arg(args: &[Object], index: usize) -> Result<&str, NativeError> {
+    match args.get(index) {
+        Some(Object::String(string)) => Ok(string),
+        found => Err(argument_mismatch("str", index, found)),
+    }
+}
+
+pub fn int_arg(args: &[Object], index: usize) -> Result<i32, NativeError> {
+    match args.get(index) {
+        Some(Object::Integer(integer)) => Ok(*integer),
+        found => Err(argument_mismatch("int", index, found)),
+    }
+}
+
+pub fn list_arg(args: &[Object], index: usize) -> Result<&[Object], NativeError> {
+    match args.get(index) {
+        Some(Object::List(objects)) => Ok(objects),
+        found => Err(argument_mismatch("list", index, found)),
+    }
+}
+
+fn argument_mismatch(expected: &str, index: usize, found: Option<&Object>) -> NativeError {
+    let found = found.map_or(Type::Void, |o| o.get_type());
+    NativeError::new(format!("Expecting argument {} to be of type `{}` but got `{}`", index + 1, expected, found))
+}
+
+pub fn list_natives() -> HashMap<String, NativeFn> {
+    NativesBuilder::stdlib().build()
+}
+
+/// The prototypes of `natives`, what the checker needs to know about them
+pub fn prototypes(natives: &HashMap<String, NativeFn>) -> HashMap<String, FunctionPrototype> {
+    natives.iter().map(|(name, native)| (name.clone(), native.prototype.clone())).collect()
+}
+
+/// Calls the native `name` of `natives` like the engine would, with no input and discarded output
+#[cfg(test)]
+fn call(natives: &mut HashMap<String, NativeFn>, name: &str, args: Vec<Object>) -> NativeResult {
+    let native = natives.get_mut(name).unwrap_or_else(|| panic!("no native `{}`", name));
+    let (mut output, mut error_output, mut input) = (std::io::sink(), std::io::sink(), std::io::empty());
+    let mut context = NativeContext {
+        caller: "main",
+        depth: 0,
+        output: &mut output,
+        error_output: &mut error_output,
+        input: &mut input,
+    };
+    (native.function)(&mut context, args)
+}
+
+/// Calls the native `name` of the standard library, see `call`
+#[cfg(test)]
+fn run(name: &str, args: impl crate::vm::IntoArgs) -> NativeResult {
+    call(&mut list_natives(), name, args.into_args())
+}
+
+/// The message of the error the native `name` of the standard library fails with
+#[cfg(test)]
+fn error(name: &str, args: impl crate::vm::IntoArgs) -> String {
+    run(name, args).unwrap_err().message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NativeError, NativesBuilder};
+    use crate::assembler::assemble;
+    use crate::vm::{Engine, RuntimeError};
+    use crate::{Object, Type};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    const HALVES: &str = "fn main arity=1 locals=1\n    LLOAD 0\n    INVOKE_NATIVE half\n    INVOKE_NATIVE half\n    \
+        RETURN\nend\n";
+
+    #[test]
+    fn natives_are_closures_that_can_fail() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        let natives = NativesBuilder::new()
+            .with("half", vec![Type::Integer], Type::Integer, move |_context, args| {
+                counter.set(counter.get() + 1);
+                match args[0] {
+                    Object::Integer(n) if n % 2 == 0 => Ok(Some(Object::Integer(n / 2))),
+                    _ => Err(NativeError::new("expecting an even number")),
+                }
+            })
+            .build();
+        let mut engine = Engine::bootstrap(assemble(HALVES).unwrap(), natives);
+        assert_eq!(engine.call::<(i32,), i32>("main", (12,)), Ok(3));
+        assert_eq!(calls.get(), 2);
+        let err =
+            RuntimeError::Native { function: "half".to_string(), message: "expecting an even number".to_string() };
+        assert_eq!(engine.call::<(i32,), i32>("main", (6,)), Err(err));
+        assert_eq!(calls.get(), 4);
+    }
+}
@@ -0,0 +1,95 @@
+use super::{int_arg, NativeError, NativesBuilder};
+use crate::{Object, Type};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// SplitMix64, small and fully specified so that a seed gives the same numbers on every platform
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `low..=high`, by scaling rather than by modulo to avoid favouring small numbers
+    fn between(&mut self, low: i32, high: i32) -> i32 {
+        let range = (high as i64 - low as i64 + 1) as u128;
+        let offset = (self.next() as u128 * range) >> 64;
+        (low as i64 + offset as i64) as i32
+    }
+}
+
+/// `seed` and `random`, sharing one generator seeded with `seed` or from the time
+pub fn register(natives: NativesBuilder, seed: Option<u64>) -> NativesBuilder {
+    let seed = seed
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64));
+    let generator = Rc::new(RefCell::new(SplitMix64::new(seed)));
+    let seeded = Rc::clone(&generator);
+    natives
+        .with("seed", vec![Type::Integer], Type::Void, move |_, args| {
+            *seeded.borrow_mut() = SplitMix64::new(int_arg(&args, 0)? as u64);
+            Ok(None)
+        })
+        .with("random", vec![Type::Integer, Type::Integer], Type::Integer, move |_, args| {
+            let (low, high) = (int_arg(&args, 0)?, int_arg(&args, 1)?);
+            if low > high {
+                return Err(NativeError::new(format!("Empty range {}..{}", low, high)));
+            }
+            Ok(Some(Object::Integer(generator.borrow_mut().between(low, high))))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stdlib::{call, NativeFn, NativesBuilder, StdlibOptions};
+    use crate::Object;
+    use std::collections::HashMap;
+
+    fn seeded(seed: u64) -> HashMap<String, NativeFn> {
+        NativesBuilder::stdlib_with(&StdlibOptions { seed: Some(seed), ..Default::default() }).build()
+    }
+
+    fn draw(natives: &mut HashMap<String, NativeFn>, count: usize) -> Vec<i32> {
+        (0..count)
+            .map(|_| match call(natives, "random", vec![Object::Integer(1), Object::Integer(100)]) {
+                Ok(Some(Object::Integer(n))) => n,
+                result => panic!("`random` returned {:?}", result),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_seed_gives_the_same_numbers() {
+        let numbers = draw(&mut seeded(42), 20);
+        assert_eq!(draw(&mut seeded(42), 20), numbers);
+        assert_ne!(draw(&mut seeded(43), 20), numbers);
+        assert!(numbers.iter().all(|n| (1..=100).contains(n)), "{:?}", numbers);
+    }
+
+    #[test]
+    fn seeding_restarts_the_sequence() {
+        let mut natives = seeded(7);
+        call(&mut natives, "seed", vec![Object::Integer(42)]).unwrap();
+        assert_eq!(draw(&mut natives, 20), draw(&mut seeded(42), 20));
+    }
+
+    #[test]
+    fn ranges_can_hold_a_single_number_but_not_none() {
+        let mut natives = seeded(42);
+        let args = vec![Object::Integer(i32::MIN), Object::Integer(i32::MIN)];
+        assert_eq!(call(&mut natives, "random", args), Ok(Some(Object::Integer(i32::MIN))));
+        let args = vec![Object::Integer(2), Object::Integer(1)];
+        assert_eq!(call(&mut natives, "random", args).unwrap_err().message, "Empty range 2..1");
+    }
+}
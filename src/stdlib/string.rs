@@ -0,0 +1,204 @@
+use super::{int_arg, list_arg, str_arg, NativeContext, NativeError, NativeResult, NativesBuilder};
+use crate::{Object, Type};
+
+/// Longest string `repeat` builds, it allocates before the engine can count the bytes against its heap limit
+const MAX_REPEAT_BYTES: usize = 1 << 20;
+
+/// Text processing natives, positions and lengths count characters rather than bytes
+pub fn register(natives: NativesBuilder) -> NativesBuilder {
+    let string = || Type::String;
+    let strings = || Type::List(Box::new(Type::String));
+    natives
+        .with("len", vec![string()], Type::Integer, len)
+        .with("substr", vec![string(), Type::Integer, Type::Integer], Type::String, substr)
+        .with("index_of", vec![string(), string()], Type::Integer, index_of)
+        .with("contains", vec![string(), string()], Type::Boolean, contains)
+        .with("starts_with", vec![string(), string()], Type::Boolean, starts_with)
+        .with("ends_with", vec![string(), string()], Type::Boolean, ends_with)
+        .with("concat", vec![string(), string()], Type::String, concat)
+        .with("split", vec![string(), string()], strings(), split)
+        .with("trim", vec![string()], Type::String, trim)
+        .with("upper", vec![string()], Type::String, upper)
+        .with("lower", vec![string()], Type::String, lower)
+        .with("repeat", vec![string(), Type::Integer], Type::String, repeat)
+        .with("to_str", vec![Type::Integer], Type::String, to_str)
+        .with("is_int", vec![string()], Type::Boolean, is_int)
+        .with("to_int", vec![string()], Type::Integer, to_int)
+        .with("count", vec![strings()], Type::Integer, count)
+        .with("at", vec![strings(), Type::Integer], Type::String, at)
+}
+
+fn integer(value: usize) -> Object {
+    Object::Integer(i32::try_from(value).unwrap_or(i32::MAX))
+}
+
+fn len(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(integer(str_arg(&args, 0)?.chars().count())))
+}
+
+/// Characters from `start` up to, not including, `end`
+fn substr(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let string = str_arg(&args, 0)?;
+    let (start, end) = (int_arg(&args, 1)?, int_arg(&args, 2)?);
+    let length = string.chars().count();
+    let range = usize::try_from(start).ok().zip(usize::try_from(end).ok());
+    match range {
+        Some((start, end)) if start <= end && end <= length => {
+            Ok(Some(Object::String(string.chars().skip(start).take(end - start).collect())))
+        }
+        _ => Err(NativeError::new(format!(
+            "Range {}..{} is out of bounds of a string of {} characters",
+            start, end, length
+        ))),
+    }
+}
+
+/// Position of the first occurrence of the needle, -1 when there's none
+fn index_of(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (string, needle) = (str_arg(&args, 0)?, str_arg(&args, 1)?);
+    let index = string.find(needle).map_or(Object::Integer(-1), |byte| integer(string[..byte].chars().count()));
+    Ok(Some(index))
+}
+
+fn contains(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::Boolean(str_arg(&args, 0)?.contains(str_arg(&args, 1)?))))
+}
+
+fn starts_with(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::Boolean(str_arg(&args, 0)?.starts_with(str_arg(&args, 1)?))))
+}
+
+fn ends_with(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::Boolean(str_arg(&args, 0)?.ends_with(str_arg(&args, 1)?))))
+}
+
+fn concat(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::String(format!("{}{}", str_arg(&args, 0)?, str_arg(&args, 1)?))))
+}
+
+fn split(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (string, separator) = (str_arg(&args, 0)?, str_arg(&args, 1)?);
+    if separator.is_empty() {
+        return Err(NativeError::new("The separator is empty"));
+    }
+    Ok(Some(Object::List(string.split(separator).map(|part| Object::String(part.to_string())).collect())))
+}
+
+fn trim(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::String(str_arg(&args, 0)?.trim().to_string())))
+}
+
+fn upper(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::String(str_arg(&args, 0)?.to_uppercase())))
+}
+
+fn lower(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::String(str_arg(&args, 0)?.to_lowercase())))
+}
+
+fn repeat(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (string, times) = (str_arg(&args, 0)?, int_arg(&args, 1)?);
+    let Ok(times) = usize::try_from(times) else {
+        return Err(NativeError::new(format!("Can't repeat a string {} times", times)));
+    };
+    if string.len().saturating_mul(times) > MAX_REPEAT_BYTES {
+        return Err(NativeError::new(format!(
+            "Repeating a string of {} bytes {} times is longer than {} bytes",
+            string.len(),
+            times,
+            MAX_REPEAT_BYTES
+        )));
+    }
+    Ok(Some(Object::String(string.repeat(times))))
+}
+
+fn to_str(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::String(int_arg(&args, 0)?.to_string())))
+}
+
+/// Whether `to_int` would succeed
+fn is_int(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(Object::Boolean(str_arg(&args, 0)?.trim().parse::<i32>().is_ok())))
+}
+
+fn to_int(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let string = str_arg(&args, 0)?;
+    match string.trim().parse::<i32>() {
+        Ok(integer) => Ok(Some(Object::Integer(integer))),
+        Err(_) => Err(NativeError::new(format!("{:?} is not an integer, check it with `is_int` first", string))),
+    }
+}
+
+fn count(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    Ok(Some(integer(list_arg(&args, 0)?.len())))
+}
+
+fn at(_: &mut NativeContext, args: Vec<Object>) -> NativeResult {
+    let (list, index) = (list_arg(&args, 0)?, int_arg(&args, 1)?);
+    match usize::try_from(index).ok().and_then(|index| list.get(index)) {
+        Some(object) => Ok(Some(object.clone())),
+        None => Err(NativeError::new(format!("Index {} is out of bounds of a list of {} items", index, list.len()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stdlib::{error, run};
+    use crate::Object;
+
+    fn string(text: &str) -> Object {
+        Object::String(text.to_string())
+    }
+
+    #[test]
+    fn substr_takes_a_range_of_characters() {
+        let args = vec![string("hello"), Object::Integer(1), Object::Integer(4)];
+        assert_eq!(run("substr", args), Ok(Some(string("ell"))));
+        let args = vec![string("hello"), Object::Integer(5), Object::Integer(5)];
+        assert_eq!(run("substr", args), Ok(Some(string(""))));
+    }
+
+    #[test]
+    fn substr_rejects_ranges_out_of_bounds() {
+        let message = error("substr", vec![string("hello"), Object::Integer(2), Object::Integer(9)]);
+        assert_eq!(message, "Range 2..9 is out of bounds of a string of 5 characters");
+        let message = error("substr", vec![string("hello"), Object::Integer(-1), Object::Integer(2)]);
+        assert_eq!(message, "Range -1..2 is out of bounds of a string of 5 characters");
+    }
+
+    #[test]
+    fn substr_rejects_reversed_ranges() {
+        let message = error("substr", vec![string("hello"), Object::Integer(3), Object::Integer(1)]);
+        assert_eq!(message, "Range 3..1 is out of bounds of a string of 5 characters");
+    }
+
+    #[test]
+    fn positions_count_characters_not_bytes() {
+        assert_eq!(run("len", vec![string("héllo")]), Ok(Some(Object::Integer(5))));
+        let args = vec![string("日本語"), Object::Integer(1), Object::Integer(3)];
+        assert_eq!(run("substr", args), Ok(Some(string("本語"))));
+        assert_eq!(run("index_of", vec![string("日本語"), string("語")]), Ok(Some(Object::Integer(2))));
+        assert_eq!(run("index_of", vec![string("日本語"), string("x")]), Ok(Some(Object::Integer(-1))));
+        assert_eq!(run("upper", vec![string("straße")]), Ok(Some(string("STRASSE"))));
+    }
+
+    #[test]
+    fn split_rejects_an_empty_separator() {
+        assert_eq!(error("split", vec![string("a,b"), string("")]), "The separator is empty");
+        let parts = vec![string("a"), string(""), string("日")];
+        assert_eq!(run("split", vec![string("a,,日"), string(",")]), Ok(Some(Object::List(parts))));
+    }
+
+    #[test]
+    fn conversions_reject_what_they_cant_represent() {
+        assert_eq!(run("is_int", vec![string("2147483648")]), Ok(Some(Object::Boolean(false))));
+        assert_eq!(error("to_int", vec![string("12a")]), "\"12a\" is not an integer, check it with `is_int` first");
+        assert_eq!(error("repeat", vec![string("ab"), Object::Integer(-1)]), "Can't repeat a string -1 times");
+        let message = error("repeat", vec![string("x"), Object::Integer(2_000_000_000)]);
+        assert_eq!(message, "Repeating a string of 1 bytes 2000000000 times is longer than 1048576 bytes");
+        let repeated = run("repeat", vec![string("ab"), Object::Integer(1 << 19)]).unwrap().unwrap();
+        assert_eq!(repeated.to_string().len(), 1 << 20);
+        let list = Object::List(vec![string("a")]);
+        assert_eq!(error("at", vec![list, Object::Integer(1)]), "Index 1 is out of bounds of a list of 1 items");
+    }
+}
@@ -0,0 +1,90 @@
+use super::{NativeError, NativesBuilder};
+use crate::{Object, Type};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+enum Clock {
+    Real {
+        started: Instant,
+    },
+    /// The wall clock stands still and every reading of `clock` advances it by one millisecond
+    Fake {
+        time: i32,
+        elapsed: Cell<i32>,
+    },
+}
+
+impl Clock {
+    fn elapsed_millis(&self) -> i32 {
+        match self {
+            Self::Real { started } => i32::try_from(started.elapsed().as_millis()).unwrap_or(i32::MAX),
+            Self::Fake { elapsed, .. } => {
+                elapsed.set(elapsed.get().saturating_add(1));
+                elapsed.get()
+            }
+        }
+    }
+
+    /// Only fits an `int` until 2038-01-19 03:14:07 UTC, `time` fails after that
+    fn seconds_since_epoch(&self) -> Result<i32, NativeError> {
+        match self {
+            Self::Real { .. } => {
+                let elapsed = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|_| NativeError::new("The system clock is set before 1970"))?;
+                i32::try_from(elapsed.as_secs())
+                    .map_err(|_| NativeError::new("The time doesn't fit in an `int` after 2038-01-19"))
+            }
+            Self::Fake { time, .. } => Ok(*time),
+        }
+    }
+}
+
+/// `clock` for measuring durations and `time` for the date, `fake_time` pins the latter and makes both deterministic.
+/// `time` returns an `int` of seconds, so it fails with a runtime error from 2038-01-19 03:14:08 UTC on
+pub fn register(natives: NativesBuilder, fake_time: Option<i32>) -> NativesBuilder {
+    let clock = Rc::new(match fake_time {
+        Some(time) => Clock::Fake { time, elapsed: Cell::new(0) },
+        None => Clock::Real { started: Instant::now() },
+    });
+    let wall_clock = Rc::clone(&clock);
+    natives.with("clock", vec![], Type::Integer, move |_, _| Ok(Some(Object::Integer(clock.elapsed_millis())))).with(
+        "time",
+        vec![],
+        Type::Integer,
+        move |_, _| Ok(Some(Object::Integer(wall_clock.seconds_since_epoch()?))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stdlib::{call, NativeFn, NativesBuilder, StdlibOptions};
+    use crate::Object;
+    use std::collections::HashMap;
+
+    fn faked(time: i32) -> HashMap<String, NativeFn> {
+        NativesBuilder::stdlib_with(&StdlibOptions { fake_time: Some(time), ..Default::default() }).build()
+    }
+
+    #[test]
+    fn a_fake_time_stands_still() {
+        let mut natives = faked(1_700_000_000);
+        for _ in 0..3 {
+            assert_eq!(call(&mut natives, "time", vec![]), Ok(Some(Object::Integer(1_700_000_000))));
+        }
+    }
+
+    #[test]
+    fn a_fake_clock_advances_a_millisecond_per_reading() {
+        let readings = |natives: &mut HashMap<String, NativeFn>| -> Vec<_> {
+            (0..3).map(|_| call(natives, "clock", vec![]).unwrap()).collect()
+        };
+        let expected: Vec<_> = (1..=3).map(|millis| Some(Object::Integer(millis))).collect();
+        let mut natives = faked(0);
+        assert_eq!(readings(&mut natives), expected);
+        call(&mut natives, "time", vec![]).unwrap();
+        assert_eq!(call(&mut natives, "clock", vec![]), Ok(Some(Object::Integer(4))));
+        assert_eq!(readings(&mut faked(0)), expected);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::bvm::{exit_on_runtime_error, exit_with_result};
+use crate::repl::format_stack;
+use bug::bytecode::Opcode;
+use bug::disassembler::{instruction, object_value};
+use bug::stdlib::NativeFn;
+use bug::vm::{Engine, Limits, RuntimeError};
+use bug::{Object, Program};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+pub struct TraceOptions {
+    /// Only trace instructions of these functions, every function when empty
+    pub functions: Vec<String>,
+    /// Stop tracing after this many traced instructions, the program keeps running
+    pub limit: Option<usize>,
+    /// One JSON object per line instead of the human readable listing
+    pub json: bool,
+}
+
+/// Runs `program`, printing every executed instruction and the operand stack after it on stderr
+pub fn run(
+    program: Program,
+    natives: HashMap<String, NativeFn>,
+    args: Vec<String>,
+    options: &TraceOptions,
+    limits: Limits,
+    source: Option<String>,
+) {
+    // Instructions are listed with the names of their callees, the engine only knows their indices
+    let listing = program.clone();
+    let mut engine = Engine::bootstrap(program, natives).with_args(args).with_limits(limits);
+    if let Some(source) = source {
+        engine = engine.with_source(source);
+    }
+    if let Err(err) = engine.start() {
+        exit_on_runtime_error(&engine, err);
+    }
+    match trace(&mut engine, &listing, options, &mut io::stderr()) {
+        Ok(None) => exit_with_result(engine.result()),
+        Ok(Some(err)) => exit_on_runtime_error(&engine, err),
+        Err(err) => {
+            eprintln!("[Error]: Couldn't write the trace: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Steps the started `engine` to the end while writing the trace to `out`, returns the runtime error that stopped it
+fn trace(
+    engine: &mut Engine,
+    program: &Program,
+    options: &TraceOptions,
+    out: &mut dyn Write,
+) -> io::Result<Option<RuntimeError>> {
+    let mut step: usize = 0;
+    let mut traced = 0;
+    loop {
+        let frame = engine.frame();
+        let name = frame.get_name().to_string();
+        let ip = frame.ip;
+        let opcode = frame.function.code.get_at(ip).cloned();
+        let depth = engine.depth();
+        let result = engine.step();
+        // After a call the frame that executed the instruction is the callee's caller
+        let call = engine.depth().saturating_sub(depth);
+        step += 1;
+
+        let selected = options.functions.is_empty() || options.functions.contains(&name);
+        if let (true, Some(opcode)) = (selected, opcode) {
+            traced += 1;
+            match options.limit {
+                // A note would break JSON lines parsers
+                Some(limit) if traced == limit + 1 && !options.json => {
+                    writeln!(out, "... trace limit of {} instructions reached", limit)?
+                }
+                Some(limit) if traced > limit => {}
+                _ => print_step(out, step, (&name, ip), &opcode, program, engine.operands(call), options.json)?,
+            }
+        }
+        match result {
+            Ok(true) => {}
+            Ok(false) => return Ok(None),
+            Err(err) => return Ok(Some(err)),
+        }
+    }
+}
+
+/// `at` is the function and offset of the instruction
+fn print_step(
+    out: &mut dyn Write,
+    step: usize,
+    at: (&str, usize),
+    opcode: &Opcode,
+    program: &Program,
+    stack: &[Object],
+    json: bool,
+) -> io::Result<()> {
+    let (name, ip) = at;
+    let instruction = instruction(opcode, program);
+    if json {
+        let stack: Vec<Value> = stack.iter().map(object_value).collect();
+        let line = json!({
+            "step": step,
+            "function": name,
+            "ip": ip,
+            "opcode": opcode.mnemonic(),
+            "instruction": instruction,
+            "stack": stack,
+        });
+        writeln!(out, "{}", line)
+    } else {
+        let location = format!("{}:{:04}", name, ip);
+        writeln!(out, "{:>6}  {:<16}  {:<24}  {}", step, location, instruction, format_stack(stack))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{trace, TraceOptions};
+    use crate::bugc::try_compile;
+    use bug::stdlib::list_natives;
+    use bug::vm::{Engine, RuntimeError};
+
+    const SOURCE: &str = "fn twice(int n) int -> n n +;\nfn main() int -> 3 @twice;\n";
+
+    fn traced(source: &str, options: TraceOptions) -> (String, Option<RuntimeError>) {
+        let program = try_compile("traced.bug", source).unwrap();
+        let mut engine = Engine::bootstrap(program.clone(), list_natives());
+        engine.start().unwrap();
+        let mut out: Vec<u8> = vec![];
+        let error = trace(&mut engine, &program, &options, &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), error)
+    }
+
+    fn options() -> TraceOptions {
+        TraceOptions { functions: vec![], limit: None, json: false }
+    }
+
+    #[test]
+    fn lists_every_instruction_with_the_stack_after_it() {
+        let expected = "     1  main:0000         IPUSH 3                   3
+     2  main:0001         INVOKE twice              (empty)
+     3  twice:0000        LLOAD 0                   3
+     4  twice:0001        LLOAD 0                   3 3
+     5  twice:0002        IADD                      6
+     6  twice:0003        RETURN                    6
+     7  main:0002         RETURN                    6
+";
+        assert_eq!(traced(SOURCE, options()), (expected.to_string(), None));
+    }
+
+    #[test]
+    fn filters_functions_as_json_lines() {
+        let options = TraceOptions { functions: vec!["twice".to_string()], limit: Some(2), json: true };
+        let expected = r#"{"function":"twice","instruction":"LLOAD 0","ip":0,"opcode":"LLOAD","stack":[3],"step":3}
+{"function":"twice","instruction":"LLOAD 0","ip":1,"opcode":"LLOAD","stack":[3,3],"step":4}
+"#;
+        assert_eq!(traced(SOURCE, options), (expected.to_string(), None));
+    }
+
+    #[test]
+    fn stops_tracing_at_the_limit() {
+        let (trace, error) = traced(SOURCE, TraceOptions { limit: Some(2), ..options() });
+        assert_eq!(trace.lines().last(), Some("... trace limit of 2 instructions reached"));
+        assert_eq!((trace.lines().count(), error), (3, None));
+    }
+
+    #[test]
+    fn traces_up_to_a_runtime_error() {
+        let (trace, error) = traced("fn main() int -> 2147483647 1 +;\n", options());
+        assert_eq!(trace.lines().count(), 3);
+        assert_eq!(error, Some(RuntimeError::IntegerOverflow { lhs: i32::MAX, rhs: 1 }));
+    }
+}
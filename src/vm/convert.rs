@@ -0,0 +1,123 @@
+use super::error::RuntimeError;
+use crate::{Object, Type};
+
+/// Arguments of `Engine::call`, in the order of the function's parameters
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Object>;
+}
+
+/// Result of `Engine::call`, built from the value the function returned if any
+pub trait FromReturn: Sized {
+    fn from_return(result: Option<Object>) -> Result<Self, RuntimeError>;
+}
+
+impl From<i32> for Object {
+    fn from(integer: i32) -> Self {
+        Self::Integer(integer)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(boolean: bool) -> Self {
+        Self::Boolean(boolean)
+    }
+}
+
+impl From<String> for Object {
+    fn from(string: String) -> Self {
+        Self::String(string)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(string: &str) -> Self {
+        Self::String(string.to_string())
+    }
+}
+
+impl IntoArgs for Vec<Object> {
+    fn into_args(self) -> Vec<Object> {
+        self
+    }
+}
+
+impl IntoArgs for &[Object] {
+    fn into_args(self) -> Vec<Object> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> IntoArgs for &[Object; N] {
+    fn into_args(self) -> Vec<Object> {
+        self.to_vec()
+    }
+}
+
+impl IntoArgs for () {
+    fn into_args(self) -> Vec<Object> {
+        vec![]
+    }
+}
+
+macro_rules! tuple_into_args {
+    ($($name:ident),+) => {
+        impl<$($name: Into<Object>),+> IntoArgs for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<Object> {
+                let ($($name,)+) = self;
+                vec![$($name.into()),+]
+            }
+        }
+    };
+}
+
+tuple_into_args!(A);
+tuple_into_args!(A, B);
+tuple_into_args!(A, B, C);
+tuple_into_args!(A, B, C, D);
+tuple_into_args!(A, B, C, D, E);
+tuple_into_args!(A, B, C, D, E, F);
+
+impl FromReturn for Option<Object> {
+    fn from_return(result: Option<Object>) -> Result<Self, RuntimeError> {
+        Ok(result)
+    }
+}
+
+/// Discards whatever the function returned
+impl FromReturn for () {
+    fn from_return(_: Option<Object>) -> Result<Self, RuntimeError> {
+        Ok(())
+    }
+}
+
+fn mismatch(expected: Type, result: Option<Object>) -> RuntimeError {
+    RuntimeError::TypeMismatch { expected, found: result.map_or(Type::Void, |o| o.get_type()) }
+}
+
+impl FromReturn for i32 {
+    fn from_return(result: Option<Object>) -> Result<Self, RuntimeError> {
+        match result {
+            Some(Object::Integer(integer)) => Ok(integer),
+            result => Err(mismatch(Type::Integer, result)),
+        }
+    }
+}
+
+impl FromReturn for bool {
+    fn from_return(result: Option<Object>) -> Result<Self, RuntimeError> {
+        match result {
+            Some(Object::Boolean(boolean)) => Ok(boolean),
+            result => Err(mismatch(Type::Boolean, result)),
+        }
+    }
+}
+
+impl FromReturn for String {
+    fn from_return(result: Option<Object>) -> Result<Self, RuntimeError> {
+        match result {
+            Some(Object::String(string)) => Ok(string),
+            result => Err(mismatch(Type::String, result)),
+        }
+    }
+}
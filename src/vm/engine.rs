@@ -0,0 +1,732 @@
+use super::convert::{FromReturn, IntoArgs};
+use super::error::RuntimeError;
+use super::frame::{Frame, MAX_LOCALS};
+use super::stack::Stack;
+use crate::bytecode::Opcode;
+use crate::highlighter::highlight_error;
+use crate::stdlib::{format, NativeContext, NativeFn};
+use crate::Program;
+use crate::{DefinedFn, Object, Pool, Type};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+/// Frames shown by a stack trace, deep recursion would print thousands
+const MAX_TRACE_FRAMES: usize = 16;
+
+/// Bounds on what a program may use, `None` means unbounded
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// Instructions executed in total
+    pub max_steps: Option<u64>,
+    /// Calls active at the same time, `main` excluded
+    pub max_call_depth: Option<usize>,
+    /// Values on the operand stack of a single frame, the whole stack is only bounded along with `max_call_depth`
+    pub max_stack: Option<usize>,
+    /// An allocation budget: bytes of strings created in total, by loading constants or returned by natives.
+    /// Bytes aren't given back when a string is dropped
+    pub max_heap_bytes: Option<usize>,
+}
+
+pub struct Engine {
+    pool: Pool,
+    /// Source file of the program, used to show where runtime errors happen
+    file_name: String,
+    /// Source the program was compiled from when the host knows it, stack traces show excerpts of it
+    source: Option<String>,
+    frame: Frame,
+    /// Locals and operands of every active call, each frame owns the values from its `base` up to the next frame's
+    stack: Vec<Object>,
+    should_halt: bool,
+    pub frame_stack: Stack<Frame>,
+    // Built-in functions like `write`, with their names
+    natives: Vec<(String, NativeFn)>,
+    /// The natives the program calls, numbered like in the program, with their index in `natives` when found
+    bound_natives: Vec<(String, Option<usize>)>,
+    // User defined functions, numbered like in the program
+    functions: Vec<Rc<DefinedFn>>,
+    limits: Limits,
+    /// Passed to `main` when it takes a parameter
+    args: Vec<String>,
+    /// Where natives write the program's output and errors and read its input
+    output: Box<dyn Write>,
+    error_output: Box<dyn Write>,
+    input: Box<dyn BufRead>,
+    steps: u64,
+    /// Bytes counted against `Limits::max_heap_bytes` so far
+    allocated_bytes: usize,
+}
+
+impl Engine {
+    pub fn bootstrap(program: Program, natives: HashMap<String, NativeFn>) -> Self {
+        let natives: Vec<(String, NativeFn)> = natives.into_iter().collect();
+        let bound_natives = bind(&natives, program.natives);
+
+        Self {
+            pool: program.pool,
+            file_name: program.file_name,
+            source: None,
+            functions: program.fns.into_iter().map(Rc::new).collect(),
+            frame: Frame::default(),
+            stack: vec![],
+            frame_stack: Stack::new(),
+            natives,
+            bound_natives,
+            should_halt: false,
+            limits: Limits::default(),
+            args: vec![],
+            output: Box::new(io::stdout()),
+            error_output: Box::new(io::stderr()),
+            input: Box::new(io::BufReader::new(io::stdin())),
+            steps: 0,
+            allocated_bytes: 0,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The arguments `main` gets when it takes a `[str]`
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Shows excerpts of `source` in stack traces, it must be what the program was compiled from.
+    /// The engine never reads the program's `file_name` itself, a binary can name any file
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Sends the program's output to `output` instead of stdout
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Sends what the program writes to its error output to `error_output` instead of stderr
+    pub fn with_error_output(mut self, error_output: impl Write + 'static) -> Self {
+        self.error_output = Box::new(error_output);
+        self
+    }
+
+    /// Reads the program's input from `input` instead of stdin
+    pub fn with_input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Box::new(input);
+        self
+    }
+
+    /// Runs `main` to completion and returns the value it returned, if any
+    pub fn run(&mut self) -> Result<Option<Object>, RuntimeError> {
+        self.start()?;
+        self.execute()?;
+        Ok(self.pop_operand())
+    }
+
+    /// Prepares `main` to run without executing anything, see `step`
+    pub fn start(&mut self) -> Result<(), RuntimeError> {
+        self.setup_main_frame()?;
+        self.should_halt = false;
+        Ok(())
+    }
+
+    /// Replaces the loaded program with `program`, usually a newer version of it with more functions
+    pub fn load(&mut self, program: Program) {
+        self.pool = program.pool;
+        self.file_name = program.file_name;
+        self.source = None;
+        self.functions = program.fns.into_iter().map(Rc::new).collect();
+        self.bound_natives = bind(&self.natives, program.natives);
+    }
+
+    /// Runs `function` as a new entry point and returns the values it left on its stack
+    pub fn eval(&mut self, name: &str, function: DefinedFn) -> Result<Vec<Object>, RuntimeError> {
+        self.stack.clear();
+        self.enter(Rc::new(function.with_name(name)))?;
+        self.execute()?;
+        Ok(self.stack.split_off(self.frame.operands_base()))
+    }
+
+    /// Runs the user defined function `name` with `args` and converts what it returned,
+    /// e.g. `engine.call::<(i32, i32), i32>("sum", (1, 2))`. Each call gets the whole of `Limits` and starts over,
+    /// abandoning whatever the engine was running, like a program paused after `start`
+    pub fn call<A: IntoArgs, R: FromReturn>(&mut self, name: &str, args: A) -> Result<R, RuntimeError> {
+        let function = self.function(name).ok_or_else(|| RuntimeError::CallUndefined(name.to_string()))?;
+        let function = Rc::clone(function);
+        let args = args.into_args();
+        if args.len() != function.arity {
+            return Err(RuntimeError::ArityMismatch {
+                function: name.to_string(),
+                expected: function.arity,
+                found: args.len(),
+            });
+        }
+        self.stack = args;
+        self.enter(function)?;
+        self.execute()?;
+        R::from_return(self.pop_operand())
+    }
+
+    /// What `main` returned, once the program halted
+    pub fn result(&self) -> Option<&Object> {
+        self.should_halt.then(|| self.operands(0).last()).flatten()
+    }
+
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// The locals of an active call, `call` counts the callers of the current frame like a stack trace does
+    pub fn locals(&self, call: usize) -> &[Object] {
+        match self.call_frame(call) {
+            Some(frame) => &self.stack[frame.base..frame.operands_base()],
+            None => &[],
+        }
+    }
+
+    /// The operand stack of an active call, bottom first, see `locals`
+    pub fn operands(&self, call: usize) -> &[Object] {
+        let Some(frame) = self.call_frame(call) else { return &[] };
+        // A caller's operands end where the arguments of its callee, now locals, start
+        let end = match call.checked_sub(1).and_then(|callee| self.call_frame(callee)) {
+            Some(callee) => callee.base,
+            None => self.stack.len(),
+        };
+        &self.stack[frame.operands_base()..end]
+    }
+
+    fn call_frame(&self, call: usize) -> Option<&Frame> {
+        match call.checked_sub(1) {
+            None => Some(&self.frame),
+            Some(caller) => self.frame_stack.inner.iter().rev().nth(caller),
+        }
+    }
+
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        self.function(name).is_some()
+    }
+
+    /// The user defined functions, in the order calls number them
+    pub fn functions(&self) -> impl Iterator<Item = &DefinedFn> {
+        self.functions.iter().map(|function| function.as_ref())
+    }
+
+    /// Calls refer to functions by index, names are only looked up from the host
+    fn function(&self, name: &str) -> Option<&Rc<DefinedFn>> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// Number of calls below the current frame
+    pub fn depth(&self) -> usize {
+        self.frame_stack.inner.len()
+    }
+
+    fn execute(&mut self) -> Result<(), RuntimeError> {
+        self.should_halt = false;
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Executes the next instruction of the current frame, returns `false` once the program halted
+    pub fn step(&mut self) -> Result<bool, RuntimeError> {
+        if !self.engine_should_run() {
+            return Ok(false);
+        }
+        self.steps += 1;
+        if let Some(max_steps) = self.limits.max_steps.filter(|max_steps| self.steps > *max_steps) {
+            return Err(RuntimeError::StepLimit(max_steps));
+        }
+        let op = self.frame.fetch_next_op().copied().ok_or(RuntimeError::FetchOutOfRange)?;
+
+        match op {
+            Opcode::NOP => self.nop(),
+            Opcode::IADD => self.iadd()?,
+            Opcode::RETURN => self.return_()?,
+            Opcode::LDC(idx) => self.ldc(idx)?,
+            Opcode::LLOAD(idx) => self.lload(idx)?,
+            Opcode::LSTORE(idx) => self.lstore(idx)?,
+            Opcode::INVOKE(index) => self.invoke(index)?,
+            Opcode::INVOKE_NATIVE(index) => self.invoke_native(index)?,
+            Opcode::IPUSH(integer) => self.ipush(integer)?,
+            Opcode::ICMPGT => self.icmpgt()?,
+            Opcode::JUMP(offset) => self.jump(offset),
+            Opcode::JUMPNOTIF(offset) => self.jumpnotif(offset)?,
+        };
+        Ok(self.engine_should_run())
+    }
+
+    fn setup_main_frame(&mut self) -> Result<(), RuntimeError> {
+        let main = self.function("main").ok_or_else(|| RuntimeError::CallUndefined("main".to_string()))?;
+        let main = Rc::clone(main);
+        self.stack.clear();
+        match main.arity {
+            0 => {}
+            1 => {
+                let args = self.args.iter().map(|arg| Object::String(arg.clone())).collect();
+                self.stack.push(Object::List(args));
+            }
+            arity => {
+                return Err(RuntimeError::ArityMismatch { function: "main".to_string(), expected: arity, found: 1 })
+            }
+        }
+        self.enter(main)
+    }
+
+    /// Starts `function` as the only active call, its arguments are already on the stack
+    fn enter(&mut self, function: Rc<DefinedFn>) -> Result<(), RuntimeError> {
+        // Limits bound each run, not the engine's lifetime
+        self.steps = 0;
+        self.allocated_bytes = 0;
+        self.frame = self.callee_frame(function)?;
+        self.frame_stack = Stack::new();
+        Ok(())
+    }
+
+    /// A frame for `function` whose first locals are the arguments on top of the stack, the others start at zero
+    fn callee_frame(&mut self, function: Rc<DefinedFn>) -> Result<Frame, RuntimeError> {
+        // Programs built by the host skip the verifier, don't trust their locals count
+        if function.max_locals > MAX_LOCALS {
+            return Err(RuntimeError::TooManyLocals(function.max_locals));
+        }
+        if function.arity > function.max_locals {
+            return Err(RuntimeError::LocalIndexOutOfRange(function.arity - 1));
+        }
+        let base = self.stack.len().checked_sub(function.arity).ok_or(RuntimeError::StackUnderflow)?;
+        self.stack.resize(base + function.max_locals, Object::Integer(0));
+        Ok(Frame::new(function, base))
+    }
+
+    fn engine_should_run(&self) -> bool {
+        !self.should_halt
+    }
+
+    fn nop(&mut self) {}
+
+    /// Pops from the operands of the current frame, never from its locals or a caller's values
+    fn pop_operand(&mut self) -> Option<Object> {
+        if self.stack.len() > self.frame.operands_base() {
+            self.stack.pop()
+        } else {
+            None
+        }
+    }
+
+    fn pop(&mut self) -> Result<Object, RuntimeError> {
+        self.pop_operand().ok_or(RuntimeError::StackUnderflow)
+    }
+
+    fn operand_count(&self) -> usize {
+        self.stack.len() - self.frame.operands_base()
+    }
+
+    fn pop_integer(&mut self) -> Result<i32, RuntimeError> {
+        match self.pop()? {
+            Object::Integer(integer) => Ok(integer),
+            o => Err(RuntimeError::TypeMismatch { expected: Type::Integer, found: o.get_type() }),
+        }
+    }
+
+    fn pop_boolean(&mut self) -> Result<bool, RuntimeError> {
+        match self.pop()? {
+            Object::Boolean(boolean) => Ok(boolean),
+            o => Err(RuntimeError::TypeMismatch { expected: Type::Boolean, found: o.get_type() }),
+        }
+    }
+
+    fn iadd(&mut self) -> Result<(), RuntimeError> {
+        let rhs_integer = self.pop_integer()?;
+        let lhs_integer = self.pop_integer()?;
+        let result = lhs_integer
+            .checked_add(rhs_integer)
+            .ok_or(RuntimeError::IntegerOverflow { lhs: lhs_integer, rhs: rhs_integer })?;
+        self.push(Object::Integer(result))
+    }
+
+    fn ldc(&mut self, idx: usize) -> Result<(), RuntimeError> {
+        let o = self.pool.get_by_index(idx).ok_or(RuntimeError::PoolIndexOutOfRange(idx))?.clone();
+        self.allocate(&o)?;
+        self.push(o)
+    }
+
+    fn invoke_native(&mut self, index: usize) -> Result<(), RuntimeError> {
+        let (name, native) = self.bound_natives.get(index).ok_or(RuntimeError::NativeIndexOutOfRange(index))?;
+        let native = native.ok_or_else(|| RuntimeError::CallUndefined(name.clone()))?;
+        let prototype = &self.natives[native].1.prototype;
+        let mut arity = prototype.arity;
+        if prototype.template {
+            arity += self.template_values(native)?;
+        }
+        if self.operand_count() < arity {
+            return Err(RuntimeError::StackUnderflow);
+        }
+        let args = self.stack.split_off(self.stack.len() - arity);
+        let mut context = NativeContext {
+            caller: self.frame.get_name(),
+            depth: self.frame_stack.inner.len(),
+            output: &mut self.output,
+            error_output: &mut self.error_output,
+            input: &mut self.input,
+        };
+        let (name, native) = &mut self.natives[native];
+        let result = (native.function)(&mut context, args)
+            .map_err(|err| RuntimeError::Native { function: name.clone(), message: err.message })?;
+        if let Some(result) = result {
+            self.allocate(&result)?;
+            self.push(result)?;
+        }
+        Ok(())
+    }
+
+    /// The number of values taken by the template on top of the stack, the native rejects anything else
+    fn template_values(&self, native: usize) -> Result<usize, RuntimeError> {
+        match self.operands(0).last() {
+            Some(Object::String(template)) => format::placeholders(template)
+                .map(|placeholders| placeholders.len())
+                .map_err(|message| RuntimeError::Native { function: self.natives[native].0.clone(), message }),
+            _ => Ok(0),
+        }
+    }
+
+    fn invoke(&mut self, index: usize) -> Result<(), RuntimeError> {
+        let callee = self.functions.get(index).ok_or(RuntimeError::FunctionIndexOutOfRange(index))?;
+        let callee = Rc::clone(callee);
+        if self.operand_count() < callee.arity {
+            return Err(RuntimeError::StackUnderflow);
+        }
+        if let Some(max_depth) =
+            self.limits.max_call_depth.filter(|max_depth| self.frame_stack.inner.len() >= *max_depth)
+        {
+            return Err(RuntimeError::CallDepthLimit(max_depth));
+        }
+        // The arguments stay where they are and become the first locals of the callee
+        let frame = self.callee_frame(callee)?;
+        let caller = std::mem::replace(&mut self.frame, frame);
+        self.frame_stack.push(caller);
+        Ok(())
+    }
+
+    fn return_(&mut self) -> Result<(), RuntimeError> {
+        if let Some(parent_frame) = self.frame_stack.pop() {
+            let result = self.pop_operand();
+            self.stack.truncate(self.frame.base);
+            self.frame = parent_frame;
+            if let Some(o) = result {
+                self.push(o)?;
+            }
+        } else {
+            // We got return on the main function, its result stays on the stack for `run`
+            self.should_halt = true;
+        }
+        Ok(())
+    }
+
+    /// Pushes onto the operand stack of the current frame, within `Limits::max_stack`
+    fn push(&mut self, o: Object) -> Result<(), RuntimeError> {
+        if let Some(max_stack) = self.limits.max_stack.filter(|max_stack| self.operand_count() >= *max_stack) {
+            return Err(RuntimeError::StackLimit(max_stack));
+        }
+        self.stack.push(o);
+        Ok(())
+    }
+
+    /// Accounts for the bytes of a string the program creates, within `Limits::max_heap_bytes`
+    fn allocate(&mut self, o: &Object) -> Result<(), RuntimeError> {
+        self.allocated_bytes += heap_size(o);
+        if let Some(max_bytes) = self.limits.max_heap_bytes.filter(|max_bytes| self.allocated_bytes > *max_bytes) {
+            return Err(RuntimeError::HeapLimit(max_bytes));
+        }
+        Ok(())
+    }
+
+    fn ipush(&mut self, integer: i32) -> Result<(), RuntimeError> {
+        self.push(Object::Integer(integer))
+    }
+
+    fn lload(&mut self, idx: usize) -> Result<(), RuntimeError> {
+        let o = self.stack[self.local(idx)?].clone();
+        self.push(o)
+    }
+
+    fn lstore(&mut self, idx: usize) -> Result<(), RuntimeError> {
+        let o = self.pop()?;
+        let local = self.local(idx)?;
+        self.stack[local] = o;
+        Ok(())
+    }
+
+    /// Where the local `idx` of the current frame is on the value stack
+    fn local(&self, idx: usize) -> Result<usize, RuntimeError> {
+        if idx < self.frame.function.max_locals {
+            Ok(self.frame.base + idx)
+        } else {
+            Err(RuntimeError::LocalIndexOutOfRange(idx))
+        }
+    }
+
+    fn icmpgt(&mut self) -> Result<(), RuntimeError> {
+        let rhs = self.pop_integer()?;
+        let lhs = self.pop_integer()?;
+        self.push(Object::Boolean(lhs > rhs))
+    }
+
+    fn jump(&mut self, offset: usize) {
+        self.frame.ip = offset;
+    }
+
+    fn jumpnotif(&mut self, offset: usize) -> Result<(), RuntimeError> {
+        let condition = self.pop_boolean()?;
+        // jump if not will jump if the condition is false
+        if !condition {
+            self.frame.ip = offset;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the natives called by a program, numbered like `names`, among the ones of the engine
+fn bind(natives: &[(String, NativeFn)], names: Vec<String>) -> Vec<(String, Option<usize>)> {
+    names
+        .into_iter()
+        .map(|name| {
+            let index = natives.iter().position(|(native, _)| *native == name);
+            (name, index)
+        })
+        .collect()
+}
+
+/// Bytes of the strings held by `o`
+fn heap_size(o: &Object) -> usize {
+    match o {
+        Object::String(string) => string.len(),
+        Object::List(objects) => objects.iter().map(heap_size).sum(),
+        _ => 0,
+    }
+}
+
+impl Engine {
+    /// Every active call, innermost first, with its source line and an excerpt of the source given to `with_source`
+    pub fn stack_trace(&self) -> String {
+        let callers = self.frame_stack.inner.iter().rev();
+        let frames = self.frame_stack.inner.len() + 1;
+        let mut trace = String::new();
+        for frame in std::iter::once(&self.frame).chain(callers).take(MAX_TRACE_FRAMES) {
+            // `ip` already points past the instruction being executed
+            let offset = frame.ip.saturating_sub(1);
+            let Some(span) = frame.function.span_at(offset) else {
+                trace.push_str(&format!("    At function `{}`, offset {:04}\n", frame.get_name(), offset));
+                continue;
+            };
+            trace.push_str(&format!(
+                "    At function `{}` ({}:{}:{})\n",
+                frame.get_name(),
+                self.file_name,
+                span.line,
+                span.column
+            ));
+            // A span past the end of the source means it isn't the one the program was compiled from
+            if let Some(source) =
+                self.source.as_ref().filter(|source| span.start <= span.end && span.end <= source.len())
+            {
+                trace.push_str(&highlight_error(source, span.start, span.end));
+            }
+        }
+        if frames > MAX_TRACE_FRAMES {
+            trace.push_str(&format!("    ... and {} more calls\n", frames - MAX_TRACE_FRAMES));
+        }
+        trace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Engine, Limits};
+    use crate::assembler::assemble;
+    use crate::bytecode::Opcode;
+    use crate::stdlib::{list_natives, NativesBuilder};
+    use crate::vm::{RuntimeError, SharedBuffer};
+    use crate::{Object, Type};
+
+    #[test]
+    fn unverified_programs_cant_ask_for_huge_frames() {
+        let program = assemble("fn main arity=0 locals=100000000000\n    RETURN\nend\n").unwrap();
+        let mut engine = Engine::bootstrap(program, list_natives());
+        assert_eq!(engine.run(), Err(RuntimeError::TooManyLocals(100_000_000_000)));
+    }
+
+    /// Runs an assembled program within `limits`, returning its error and exit code
+    fn exceed(source: &str, limits: Limits) -> (RuntimeError, i32) {
+        let mut engine = Engine::bootstrap(assemble(source).unwrap(), list_natives()).with_limits(limits);
+        let err = engine.run().unwrap_err();
+        let code = err.exit_code();
+        (err, code)
+    }
+
+    const FOREVER: &str = "fn main arity=0 locals=0\nloop:\n    JUMP loop\nend\n";
+    const RECURSE: &str = "fn f arity=0 locals=0\n    INVOKE f\n    RETURN\nend\n\n\
+        fn main arity=0 locals=0\n    INVOKE f\n    RETURN\nend\n";
+
+    #[test]
+    fn stops_after_max_steps() {
+        let limits = Limits { max_steps: Some(10), ..Limits::default() };
+        assert_eq!(exceed(FOREVER, limits), (RuntimeError::StepLimit(10), 3));
+    }
+
+    #[test]
+    fn stops_calls_nested_deeper_than_max_call_depth() {
+        let limits = Limits { max_call_depth: Some(5), ..Limits::default() };
+        assert_eq!(exceed(RECURSE, limits), (RuntimeError::CallDepthLimit(5), 4));
+    }
+
+    #[test]
+    fn stops_a_frame_pushing_more_than_max_stack() {
+        let source = "fn main arity=0 locals=0\n    IPUSH 1\n    IPUSH 2\n    IPUSH 3\n    RETURN\nend\n";
+        let limits = Limits { max_stack: Some(2), ..Limits::default() };
+        assert_eq!(exceed(source, limits), (RuntimeError::StackLimit(2), 5));
+    }
+
+    #[test]
+    fn counts_every_string_created_against_max_heap_bytes() {
+        // Each LDC creates the 4 bytes again, even though the previous ones were dropped
+        let source =
+            "const str \"abcd\"\n\nfn main arity=0 locals=1\nloop:\n    LDC #0\n    LSTORE 0\n    JUMP loop\nend\n";
+        let limits = Limits { max_heap_bytes: Some(10), ..Limits::default() };
+        assert_eq!(exceed(source, limits), (RuntimeError::HeapLimit(10), 6));
+    }
+
+    #[test]
+    fn malformed_programs_fail_instead_of_panicking() {
+        let cases = [
+            (Opcode::IADD, RuntimeError::StackUnderflow),
+            (Opcode::LDC(7), RuntimeError::PoolIndexOutOfRange(7)),
+            (Opcode::LLOAD(1), RuntimeError::LocalIndexOutOfRange(1)),
+            (Opcode::LSTORE(0), RuntimeError::StackUnderflow),
+            (Opcode::INVOKE(7), RuntimeError::FunctionIndexOutOfRange(7)),
+            (Opcode::INVOKE_NATIVE(7), RuntimeError::NativeIndexOutOfRange(7)),
+            (Opcode::JUMPNOTIF(0), RuntimeError::StackUnderflow),
+            (Opcode::JUMP(7), RuntimeError::FetchOutOfRange),
+        ];
+        for (opcode, expected) in cases {
+            let mut program = assemble("fn main arity=0 locals=1\n    NOP\n    RETURN\nend\n").unwrap();
+            program.fns[0].code.code[0] = opcode;
+            let mut engine = Engine::bootstrap(program, list_natives());
+            assert_eq!(engine.run(), Err(expected), "{:?}", opcode);
+            // Without spans nor a source the trace still names where it stopped
+            assert!(engine.stack_trace().starts_with("    At function `main`"));
+        }
+    }
+
+    const MAX: &str =
+        "fn max arity=2 locals=2\n    LLOAD 0\n    LLOAD 1\n    ICMPGT\n    JUMPNOTIF rhs\n    LLOAD 0\n    \
+        RETURN\nrhs:\n    LLOAD 1\n    RETURN\nend\n";
+
+    #[test]
+    fn calls_functions_with_tuples_and_converts_their_result() {
+        let mut engine = Engine::bootstrap(assemble(MAX).unwrap(), list_natives());
+        assert_eq!(engine.call::<(i32, i32), i32>("max", (3, 9)), Ok(9));
+        assert_eq!(
+            engine.call::<_, Option<Object>>("max", &[Object::Integer(4), Object::Integer(1)]),
+            Ok(Some(4.into()))
+        );
+        assert_eq!(
+            engine.call::<_, String>("max", (3, 9)),
+            Err(RuntimeError::TypeMismatch { expected: Type::String, found: Type::Integer })
+        );
+    }
+
+    #[test]
+    fn checks_the_arity_of_calls() {
+        let mut engine = Engine::bootstrap(assemble(MAX).unwrap(), list_natives());
+        assert_eq!(
+            engine.call::<_, i32>("max", (3,)),
+            Err(RuntimeError::ArityMismatch { function: "max".to_string(), expected: 2, found: 1 })
+        );
+        assert_eq!(engine.call::<_, i32>("min", (3, 9)), Err(RuntimeError::CallUndefined("min".to_string())));
+    }
+
+    #[test]
+    fn every_call_gets_the_whole_step_limit() {
+        // `max` executes 6 instructions when its first argument is the smaller one
+        let limits = Limits { max_steps: Some(6), ..Limits::default() };
+        let mut engine = Engine::bootstrap(assemble(MAX).unwrap(), list_natives()).with_limits(limits);
+        for _ in 0..3 {
+            assert_eq!(engine.call::<(i32, i32), i32>("max", (3, 9)), Ok(9));
+        }
+    }
+
+    #[test]
+    fn a_call_abandons_a_paused_program() {
+        let source =
+            format!("{}\nfn main arity=0 locals=0\n    IPUSH 1\n    IPUSH 2\n    INVOKE max\n    RETURN\nend\n", MAX);
+        let mut engine = Engine::bootstrap(assemble(&source).unwrap(), list_natives());
+        engine.start().unwrap();
+        engine.step().unwrap();
+        assert_eq!(engine.call::<(i32, i32), i32>("max", (5, 4)), Ok(5));
+        assert_eq!((engine.depth(), engine.operands(0)), (0, &[][..]));
+    }
+
+    #[test]
+    fn natives_missing_at_load_fail_when_called() {
+        let source = "fn main arity=0 locals=0\n    IPUSH 1\n    INVOKE_NATIVE write\n    RETURN\nend\n";
+        let mut engine = Engine::bootstrap(assemble(source).unwrap(), NativesBuilder::new().build());
+        assert_eq!(engine.run(), Err(RuntimeError::CallUndefined("write".to_string())));
+    }
+
+    #[test]
+    fn runs_recursive_functions() {
+        let fib = include_str!("../../examples/fib.basm").replace("IPUSH 30", "IPUSH 15");
+        let output = SharedBuffer::new();
+        let mut engine = Engine::bootstrap(assemble(&fib).unwrap(), list_natives()).with_output(output.clone());
+        assert_eq!(engine.run(), Ok(None));
+        assert_eq!(output.contents(), "610\n");
+        assert_eq!(engine.call::<(i32, i32), i32>("fib", (2, 20)), Ok(6765));
+    }
+
+    const NESTED: &str = "fn add arity=2 locals=3\n    LLOAD 0\n    LLOAD 1\n    IADD\n    RETURN\nend\n\n\
+        fn main arity=0 locals=1\n    IPUSH 7\n    IPUSH 1\n    IPUSH 2\n    INVOKE add\n    RETURN\nend\n";
+
+    #[test]
+    fn arguments_become_the_first_locals_of_the_callee() {
+        let mut engine = Engine::bootstrap(assemble(NESTED).unwrap(), list_natives());
+        engine.start().unwrap();
+        for _ in 0..4 {
+            engine.step().unwrap();
+        }
+        // The arguments left the caller's operands, the callee's other locals start at zero
+        assert_eq!(engine.depth(), 1);
+        assert_eq!(engine.locals(0), [Object::Integer(1), Object::Integer(2), Object::Integer(0)]);
+        assert_eq!((engine.operands(0), engine.operands(1)), (&[][..], &[Object::Integer(7)][..]));
+    }
+
+    #[test]
+    fn returning_drops_the_frame_of_the_callee() {
+        let mut engine = Engine::bootstrap(assemble(NESTED).unwrap(), list_natives());
+        engine.start().unwrap();
+        for _ in 0..8 {
+            engine.step().unwrap();
+        }
+        // Only the caller's local, its operand and the result are left
+        assert_eq!(engine.depth(), 0);
+        assert_eq!(engine.locals(0), [Object::Integer(0)]);
+        assert_eq!(engine.operands(0), [Object::Integer(7), Object::Integer(3)]);
+        assert_eq!(engine.step(), Ok(false));
+        assert_eq!(engine.result(), Some(&Object::Integer(3)));
+    }
+
+    #[test]
+    fn max_stack_counts_the_operands_of_the_current_frame() {
+        // `main` holds 3 values when `add` pushes its 2 operands, 5 in all
+        let limits = Limits { max_stack: Some(3), ..Limits::default() };
+        let mut engine = Engine::bootstrap(assemble(NESTED).unwrap(), list_natives()).with_limits(limits);
+        assert_eq!(engine.run(), Ok(Some(Object::Integer(3))));
+        let limits = Limits { max_stack: Some(2), ..Limits::default() };
+        let mut engine = Engine::bootstrap(assemble(NESTED).unwrap(), list_natives()).with_limits(limits);
+        assert_eq!(engine.run(), Err(RuntimeError::StackLimit(2)));
+    }
+}
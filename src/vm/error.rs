@@ -0,0 +1,104 @@
+use super::frame::MAX_LOCALS;
+use crate::Type;
+use std::fmt;
+
+/// Exit codes of the runtime errors raised when a limit is exceeded
+pub const EXIT_STEP_LIMIT: i32 = 3;
+pub const EXIT_CALL_DEPTH_LIMIT: i32 = 4;
+pub const EXIT_STACK_LIMIT: i32 = 5;
+pub const EXIT_HEAP_LIMIT: i32 = 6;
+
+/// Why the engine stopped before `main` returned
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    StackUnderflow,
+    CallUndefined(String),
+    /// A function was called from the host with the wrong number of arguments
+    ArityMismatch {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A native function reported a failure
+    Native {
+        function: String,
+        message: String,
+    },
+    PoolIndexOutOfRange(usize),
+    FunctionIndexOutOfRange(usize),
+    NativeIndexOutOfRange(usize),
+    /// An addition whose result doesn't fit in an `int`
+    IntegerOverflow {
+        lhs: i32,
+        rhs: i32,
+    },
+    LocalIndexOutOfRange(usize),
+    /// A function asked for more than `MAX_LOCALS` locals
+    TooManyLocals(usize),
+    /// The next instruction is past the end of the function
+    FetchOutOfRange,
+    /// An instruction found a value of the wrong type on the stack, or a function returned one to the host
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+    },
+    StepLimit(u64),
+    CallDepthLimit(usize),
+    StackLimit(usize),
+    HeapLimit(usize),
+    /// `main` returned an `int` that can't be the exit code of a process
+    ExitCodeOutOfRange(i32),
+}
+
+impl RuntimeError {
+    /// Exit code for command line tools, each limit has its own so that scripts can tell them apart
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::StepLimit(_) => EXIT_STEP_LIMIT,
+            Self::CallDepthLimit(_) => EXIT_CALL_DEPTH_LIMIT,
+            Self::StackLimit(_) => EXIT_STACK_LIMIT,
+            Self::HeapLimit(_) => EXIT_HEAP_LIMIT,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StackUnderflow => write!(f, "Stack underflow"),
+            Self::CallUndefined(name) => write!(f, "Call to undefined function `{}`", name),
+            Self::ArityMismatch { function, expected, found } => {
+                write!(f, "Function `{}` expects {} arguments but got {}", function, expected, found)
+            }
+            Self::Native { function, message } => write!(f, "Native function `{}` failed: {}", function, message),
+            Self::IntegerOverflow { lhs, rhs } => write!(f, "Integer overflow in {} + {}", lhs, rhs),
+            Self::PoolIndexOutOfRange(index) => write!(f, "Constant pool index {} out of range", index),
+            Self::FunctionIndexOutOfRange(index) => write!(f, "Function index {} out of range", index),
+            Self::NativeIndexOutOfRange(index) => write!(f, "Native index {} out of range", index),
+            Self::LocalIndexOutOfRange(index) => write!(f, "Local index {} out of range", index),
+            Self::TooManyLocals(locals) => {
+                write!(f, "A function has {} locals, more than the {} allowed", locals, MAX_LOCALS)
+            }
+            Self::FetchOutOfRange => write!(f, "Failed to fetch the next instruction"),
+            Self::TypeMismatch { expected, found } => {
+                write!(f, "Expecting a value of type `{}` but got `{}`", expected, found)
+            }
+            Self::StepLimit(max_steps) => {
+                write!(f, "Step limit exceeded, executed more than {} instructions", max_steps)
+            }
+            Self::CallDepthLimit(max_depth) => {
+                write!(f, "Call depth limit exceeded, more than {} nested calls", max_depth)
+            }
+            Self::StackLimit(max_stack) => {
+                write!(f, "Stack limit exceeded, more than {} values on the stack", max_stack)
+            }
+            Self::HeapLimit(max_bytes) => {
+                write!(f, "Heap limit exceeded, allocated more than {} bytes of strings", max_bytes)
+            }
+            Self::ExitCodeOutOfRange(code) => write!(f, "`main` returned {}, exit codes go from 0 to 255", code),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
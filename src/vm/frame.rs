@@ -0,0 +1,42 @@
+use crate::bytecode::Opcode;
+use crate::DefinedFn;
+use std::rc::Rc;
+
+/// Locals a function may have, their index fits in 16 bits like on the JVM
+pub const MAX_LOCALS: usize = u16::MAX as usize;
+
+/// An active call, its locals and operands live on the value stack of the engine starting at `base`
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub ip: usize,
+    pub function: Rc<DefinedFn>,
+    /// Index of the first local on the value stack, the arguments the caller pushed become the first locals
+    pub base: usize,
+}
+
+impl Frame {
+    pub fn new(function: Rc<DefinedFn>, base: usize) -> Self {
+        Self { ip: 0, function, base }
+    }
+
+    pub fn fetch_next_op(&mut self) -> Option<&Opcode> {
+        let instr = self.function.code.get_at(self.ip);
+        self.ip += 1;
+        instr
+    }
+
+    /// Index of the first operand on the value stack, right after the locals
+    pub fn operands_base(&self) -> usize {
+        self.base + self.function.max_locals
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.function.name
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new(Rc::new(DefinedFn::default()), 0)
+    }
+}
@@ -0,0 +1,12 @@
+pub mod convert;
+pub mod engine;
+pub mod error;
+pub mod frame;
+pub mod stack;
+pub mod streams;
+pub mod verifier;
+
+pub use convert::{FromReturn, IntoArgs};
+pub use engine::{Engine, Limits};
+pub use error::RuntimeError;
+pub use streams::SharedBuffer;
@@ -20,3 +20,9 @@ impl<T: Clone + Sized> Stack<T> {
       self.inner.is_empty()
     } */
 }
+
+impl<T: Clone + Sized> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
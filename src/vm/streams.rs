@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// An in memory output stream that stays readable after being handed to an engine
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer {
+    inner: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.inner.borrow()).into_owned()
+    }
+
+    /// Everything written since the last `take`, leaving the buffer empty
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.inner.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedBuffer;
+    use crate::assembler::assemble;
+    use crate::stdlib::list_natives;
+    use crate::vm::Engine;
+
+    const ECHO: &str = "fn main arity=0 locals=0\n    INVOKE_NATIVE read_line\n    INVOKE_NATIVE write\n    \
+        INVOKE_NATIVE read_line\n    INVOKE_NATIVE write_err\n    INVOKE_NATIVE read_line\n    INVOKE_NATIVE write\n    \
+        RETURN\nend\n";
+
+    #[test]
+    fn programs_read_and_write_the_streams_of_the_host() {
+        let (output, error_output) = (SharedBuffer::new(), SharedBuffer::new());
+        let mut engine = Engine::bootstrap(assemble(ECHO).unwrap(), list_natives())
+            .with_input("ada\r\nbob\n".as_bytes())
+            .with_output(output.clone())
+            .with_error_output(error_output.clone());
+        assert_eq!(engine.run(), Ok(None));
+        // The input ran out before the last line, which reads as empty
+        assert_eq!(output.take(), "ada\n\n");
+        assert_eq!((output.contents(), error_output.contents()), (String::new(), "bob\n".to_string()));
+    }
+}
@@ -0,0 +1,521 @@
+use super::frame::MAX_LOCALS;
+use crate::bytecode::Opcode;
+use crate::stdlib::{format, NativeFn};
+use crate::{DefinedFn, Object, Program, Type};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Checks that `program` can't make the engine misbehave: jump targets, pool and local indices and
+/// callees must exist, and every instruction must see the same stack shape on every path reaching it
+pub fn verify(program: &Program, natives: &HashMap<String, NativeFn>) -> Result<(), VerifyError> {
+    if program.function_index("main").is_none() {
+        return Err(VerifyError::new("main", None, "The program has no `main` function".to_string()));
+    }
+    let mut verifier = Verifier { program, natives, summaries: vec![None; program.fns.len()] };
+    let callers = callers(program);
+    let mut errors: Vec<Option<VerifyError>> = (0..program.fns.len()).map(|_| None).collect();
+    // Summaries start from "never returns" and only grow, a function is analyzed again whenever one it calls
+    // changes, so each one was last analyzed with the final summaries of its callees
+    let mut worklist: Vec<usize> = (0..program.fns.len()).rev().collect();
+    while let Some(index) = worklist.pop() {
+        match verifier.analyze(&program.fns[index]) {
+            Ok(returns) => {
+                errors[index] = None;
+                if verifier.summaries[index] != returns {
+                    verifier.summaries[index] = returns;
+                    for caller in &callers[index] {
+                        if !worklist.contains(caller) {
+                            worklist.push(*caller);
+                        }
+                    }
+                }
+            }
+            Err(err) => errors[index] = Some(err),
+        }
+    }
+    match errors.into_iter().flatten().next() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// The functions calling each function, by index
+fn callers(program: &Program) -> Vec<Vec<usize>> {
+    let mut callers: Vec<Vec<usize>> = vec![vec![]; program.fns.len()];
+    for (caller, function) in program.fns.iter().enumerate() {
+        for opcode in &function.code.code {
+            if let Opcode::INVOKE(callee) = opcode {
+                if let Some(callers) = callers.get_mut(*callee).filter(|callers| !callers.contains(&caller)) {
+                    callers.push(caller);
+                }
+            }
+        }
+    }
+    callers
+}
+
+#[derive(Debug)]
+pub struct VerifyError {
+    pub function: String,
+    pub offset: Option<usize>,
+    pub message: String,
+}
+
+impl VerifyError {
+    fn new(function: &str, offset: Option<usize>, message: String) -> Self {
+        Self { function: function.to_string(), offset, message }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "In function `{}` at offset {:04}: {}", self.function, offset, self.message),
+            None => write!(f, "In function `{}`: {}", self.function, self.message),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// What the verifier knows about a value, parameters could be anything
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Integer,
+    String,
+    Boolean,
+    List,
+    Unknown,
+}
+
+impl Value {
+    fn of_object(object: &Object) -> Self {
+        match object {
+            Object::Integer(_) => Self::Integer,
+            Object::String(_) => Self::String,
+            Object::Boolean(_) => Self::Boolean,
+            Object::List(_) => Self::List,
+        }
+    }
+
+    fn of_type(typ: &Type) -> Option<Self> {
+        match typ {
+            Type::Void => None,
+            Type::Integer => Some(Self::Integer),
+            Type::String => Some(Self::String),
+            Type::Boolean => Some(Self::Boolean),
+            Type::List(_) => Some(Self::List),
+        }
+    }
+
+    fn accepts(self, other: Value) -> bool {
+        self == Self::Unknown || other == Self::Unknown || self == other
+    }
+
+    fn join(self, other: Value) -> Option<Value> {
+        match (self, other) {
+            _ if self == other => Some(self),
+            (Self::Unknown, _) | (_, Self::Unknown) => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer => write!(f, "int"),
+            Self::String => write!(f, "str"),
+            Self::Boolean => write!(f, "bool"),
+            Self::List => write!(f, "list"),
+            Self::Unknown => write!(f, "any"),
+        }
+    }
+}
+
+/// What a function leaves for its caller
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Returns {
+    Nothing,
+    Value(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    stack: Vec<Value>,
+    locals: Vec<Value>,
+    /// Pool index of the string on top of the stack, when the last instruction loaded it
+    literal: Option<usize>,
+}
+
+struct Verifier<'a> {
+    program: &'a Program,
+    natives: &'a HashMap<String, NativeFn>,
+    /// What each function returns as far as known, by index, `None` until a `RETURN` of it is reachable
+    summaries: Vec<Option<Returns>>,
+}
+
+impl<'a> Verifier<'a> {
+    /// What `function` returns given the current summaries, `None` when it never returns
+    fn analyze(&self, function: &DefinedFn) -> Result<Option<Returns>, VerifyError> {
+        let code = &function.code.code;
+        let error = |offset: Option<usize>, message: String| VerifyError::new(&function.name, offset, message);
+        if code.is_empty() {
+            return Err(error(None, "Has no instructions".to_string()));
+        }
+        if function.max_locals > MAX_LOCALS {
+            return Err(error(
+                None,
+                format!("Has {} locals, more than the {} allowed", function.max_locals, MAX_LOCALS),
+            ));
+        }
+        if function.arity > function.max_locals {
+            return Err(error(
+                None,
+                format!("Has {} parameters but only {} locals", function.arity, function.max_locals),
+            ));
+        }
+        for (offset, opcode) in code.iter().enumerate() {
+            self.check_operands(opcode, function).map_err(|message| error(Some(offset), message))?;
+        }
+
+        let mut locals = vec![Value::Unknown; function.arity];
+        // The engine initializes the other locals with zeros
+        locals.resize(function.max_locals, Value::Integer);
+        let mut states: Vec<Option<State>> = vec![None; code.len()];
+        states[0] = Some(State { stack: vec![], locals, literal: None });
+        let mut worklist: Vec<usize> = vec![0];
+        let mut returns: Option<Returns> = None;
+
+        while let Some(offset) = worklist.pop() {
+            let mut state = states[offset].clone().unwrap();
+            let successors = self
+                .step(&code[offset], offset, &mut state, &mut returns)
+                .map_err(|message| error(Some(offset), message))?;
+            for successor in successors {
+                if successor >= code.len() {
+                    return Err(error(Some(offset), "Execution runs past the last instruction".to_string()));
+                }
+                let merged = match &states[successor] {
+                    None => state.clone(),
+                    Some(previous) => merge(previous, &state).map_err(|message| error(Some(successor), message))?,
+                };
+                if states[successor].as_ref() != Some(&merged) {
+                    states[successor] = Some(merged);
+                    worklist.push(successor);
+                }
+            }
+        }
+        Ok(returns)
+    }
+
+    fn check_operands(&self, opcode: &Opcode, function: &DefinedFn) -> Result<(), String> {
+        match opcode {
+            Opcode::JUMP(target) | Opcode::JUMPNOTIF(target) if *target >= function.code.code.len() => Err(format!(
+                "Jump target {} is out of range, the function has {} instructions",
+                target,
+                function.code.code.len()
+            )),
+            Opcode::LDC(index) if *index >= self.program.pool.entries.len() => Err(format!(
+                "Constant #{} is out of range, the pool has {} entries",
+                index,
+                self.program.pool.entries.len()
+            )),
+            Opcode::LLOAD(index) | Opcode::LSTORE(index) if *index >= function.max_locals => {
+                Err(format!("Local {} is out of range, the function has {} locals", index, function.max_locals))
+            }
+            Opcode::INVOKE(index) if *index >= self.program.fns.len() => Err(format!(
+                "Function #{} is out of range, the program has {} functions",
+                index,
+                self.program.fns.len()
+            )),
+            Opcode::INVOKE_NATIVE(index) => match self.program.natives.get(*index) {
+                None => Err(format!(
+                    "Native #{} is out of range, the program calls {} natives",
+                    index,
+                    self.program.natives.len()
+                )),
+                Some(name) if !self.natives.contains_key(name) => Err(format!("Call to undefined function `{}`", name)),
+                Some(_) => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies `opcode` to `state` and returns the offsets that may run next
+    fn step(
+        &self,
+        opcode: &Opcode,
+        offset: usize,
+        state: &mut State,
+        returns: &mut Option<Returns>,
+    ) -> Result<Vec<usize>, String> {
+        let literal = state.literal.take();
+        match opcode {
+            Opcode::NOP => {}
+            Opcode::IADD => {
+                pop(state, Value::Integer)?;
+                pop(state, Value::Integer)?;
+                state.stack.push(Value::Integer);
+            }
+            Opcode::ICMPGT => {
+                pop(state, Value::Integer)?;
+                pop(state, Value::Integer)?;
+                state.stack.push(Value::Boolean);
+            }
+            Opcode::RETURN => {
+                let result = state.stack.last().map_or(Returns::Nothing, |value| Returns::Value(*value));
+                *returns = Some(match (*returns, result) {
+                    (None, result) => result,
+                    (Some(Returns::Nothing), Returns::Nothing) => Returns::Nothing,
+                    (Some(Returns::Value(lhs)), Returns::Value(rhs)) => {
+                        Returns::Value(lhs.join(rhs).unwrap_or(Value::Unknown))
+                    }
+                    _ => return Err("Returns a value on some paths only".to_string()),
+                });
+                return Ok(vec![]);
+            }
+            // Nothing runs after a call that never returns
+            Opcode::INVOKE(index) if !self.invoke(*index, state)? => return Ok(vec![]),
+            Opcode::INVOKE(_) => {}
+            Opcode::INVOKE_NATIVE(index) => self.invoke_native(&self.program.natives[*index], state, literal)?,
+            Opcode::LDC(index) => {
+                let object = &self.program.pool.entries[*index];
+                state.stack.push(Value::of_object(object));
+                state.literal = matches!(object, Object::String(_)).then_some(*index);
+            }
+            Opcode::LLOAD(index) => state.stack.push(state.locals[*index]),
+            Opcode::LSTORE(index) => state.locals[*index] = pop(state, Value::Unknown)?,
+            Opcode::IPUSH(_) => state.stack.push(Value::Integer),
+            Opcode::JUMP(target) => return Ok(vec![*target]),
+            Opcode::JUMPNOTIF(target) => {
+                pop(state, Value::Boolean)?;
+                return Ok(vec![*target, offset + 1]);
+            }
+        }
+        Ok(vec![offset + 1])
+    }
+
+    fn invoke_native(&self, callee: &str, state: &mut State, literal: Option<usize>) -> Result<(), String> {
+        let prototype = &self.natives[callee].prototype;
+        if prototype.template {
+            return self.invoke_template(callee, state, literal, &prototype.return_type);
+        }
+        let typed = prototype.parameters_types.len() == prototype.arity;
+        for index in (0..prototype.arity).rev() {
+            let expected = if typed { Value::of_type(&prototype.parameters_types[index]) } else { None };
+            pop(state, expected.unwrap_or(Value::Unknown))?;
+        }
+        state.stack.extend(Value::of_type(&prototype.return_type));
+        Ok(())
+    }
+
+    /// Returns whether the call may return
+    fn invoke(&self, callee: usize, state: &mut State) -> Result<bool, String> {
+        let arity = self.program.fns[callee].arity;
+        for _ in 0..arity {
+            pop(state, Value::Unknown)?;
+        }
+        match self.summaries[callee] {
+            None => return Ok(false),
+            Some(Returns::Nothing) => {}
+            Some(Returns::Value(value)) => state.stack.push(value),
+        }
+        Ok(true)
+    }
+
+    /// The values a template native takes are only known from a template loaded right before the call
+    fn invoke_template(
+        &self,
+        callee: &str,
+        state: &mut State,
+        literal: Option<usize>,
+        return_type: &Type,
+    ) -> Result<(), String> {
+        let Some(Object::String(template)) = literal.map(|index| &self.program.pool.entries[index]) else {
+            return Err(format!("The template of `{}` must be a string constant loaded right before the call", callee));
+        };
+        pop(state, Value::String)?;
+        for placeholder in format::placeholders(template)?.iter().rev() {
+            pop(state, if placeholder.zeros { Value::Integer } else { Value::Unknown })?;
+        }
+        state.stack.extend(Value::of_type(return_type));
+        Ok(())
+    }
+}
+
+fn pop(state: &mut State, expected: Value) -> Result<Value, String> {
+    let value = state.stack.pop().ok_or_else(|| "Stack underflow".to_string())?;
+    if !expected.accepts(value) {
+        return Err(format!("Expecting a value of type `{}` on the stack but got `{}`", expected, value));
+    }
+    Ok(value)
+}
+
+fn merge(previous: &State, incoming: &State) -> Result<State, String> {
+    if previous.stack.len() != incoming.stack.len() {
+        return Err(format!(
+            "The stack holds {} values on one path and {} on another",
+            previous.stack.len(),
+            incoming.stack.len()
+        ));
+    }
+    let mut stack: Vec<Value> = vec![];
+    for (lhs, rhs) in previous.stack.iter().zip(&incoming.stack) {
+        let joined =
+            lhs.join(*rhs).ok_or_else(|| format!("The stack holds `{}` on one path and `{}` on another", lhs, rhs))?;
+        stack.push(joined);
+    }
+    // Locals may legitimately hold different values, they only lose their type
+    let locals = previous
+        .locals
+        .iter()
+        .zip(&incoming.locals)
+        .map(|(lhs, rhs)| lhs.join(*rhs).unwrap_or(Value::Unknown))
+        .collect();
+    let literal = previous.literal.filter(|index| incoming.literal == Some(*index));
+    Ok(State { stack, locals, literal })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::verify;
+    use crate::assembler::assemble;
+    use crate::stdlib::list_natives;
+
+    /// Verifies `main`, given the body of its listing
+    fn verify_main(locals: usize, body: &str) -> Result<(), String> {
+        let source = format!("const str \"text\"\nfn main arity=0 locals={}\n{}\nend\n", locals, body);
+        verify(&assemble(&source).unwrap(), &list_natives()).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn accepts_consistent_code() {
+        assert_eq!(
+            verify_main(1, "IPUSH 1\nLSTORE 0\nLLOAD 0\nIPUSH 2\nICMPGT\nJUMPNOTIF done\nNOP\ndone:\nRETURN"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_stack_underflow() {
+        assert_eq!(
+            verify_main(0, "IPUSH 1\nIADD\nRETURN"),
+            Err("In function `main` at offset 0001: Stack underflow".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_type_mismatches() {
+        assert_eq!(
+            verify_main(0, "LDC #0\nIPUSH 1\nIADD\nRETURN"),
+            Err("In function `main` at offset 0002: Expecting a value of type `int` on the stack but got `str`"
+                .to_string())
+        );
+        assert_eq!(
+            verify_main(0, "IPUSH 1\nJUMPNOTIF end\nend:\nRETURN"),
+            Err("In function `main` at offset 0001: Expecting a value of type `bool` on the stack but got `int`"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_different_stack_heights_at_a_jump_target() {
+        let body = "IPUSH 1\nIPUSH 2\nICMPGT\nJUMPNOTIF join\nIPUSH 3\njoin:\nRETURN";
+        assert_eq!(
+            verify_main(0, body),
+            Err("In function `main` at offset 0005: The stack holds 0 values on one path and 1 on another".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_jumps_out_of_range() {
+        assert_eq!(
+            verify_main(0, "JUMP 7\nRETURN"),
+            Err("In function `main` at offset 0000: Jump target 7 is out of range, the function has 2 instructions"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_locals_out_of_range() {
+        assert_eq!(
+            verify_main(1, "LLOAD 1\nRETURN"),
+            Err("In function `main` at offset 0000: Local 1 is out of range, the function has 1 locals".to_string())
+        );
+        assert_eq!(
+            verify_main(0, "IPUSH 1\nLSTORE 0\nRETURN"),
+            Err("In function `main` at offset 0001: Local 0 is out of range, the function has 0 locals".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_too_many_locals() {
+        assert_eq!(
+            verify_main(100_000_000_000, "RETURN"),
+            Err("In function `main`: Has 100000000000 locals, more than the 65535 allowed".to_string())
+        );
+    }
+
+    fn verify_source(source: &str) -> Result<(), String> {
+        verify(&assemble(source).unwrap(), &list_natives()).map_err(|err| err.to_string())
+    }
+
+    const EVEN_ODD: &str = "
+fn even arity=1 locals=1
+    LLOAD 0
+    IPUSH 0
+    ICMPGT
+    JUMPNOTIF zero
+    LLOAD 0
+    INVOKE odd
+    RETURN
+zero:
+    IPUSH 1
+    RETURN
+end
+fn odd arity=1 locals=1
+    LLOAD 0
+    INVOKE even
+    RETURN
+end
+";
+
+    #[test]
+    fn follows_results_through_mutual_recursion() {
+        let main = "fn main arity=0 locals=0\n    IPUSH 3\n    INVOKE odd\n    IPUSH 1\n    IADD\n    RETURN\nend\n";
+        assert_eq!(verify_source(&format!("{}{}", EVEN_ODD, main)), Ok(()));
+        let main = "fn main arity=0 locals=0\n    IPUSH 3\n    INVOKE odd\n    JUMPNOTIF end\nend:\n    RETURN\nend\n";
+        assert_eq!(
+            verify_source(&format!("{}{}", EVEN_ODD, main)),
+            Err("In function `main` at offset 0002: Expecting a value of type `bool` on the stack but got `int`"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn revisits_callees_analyzed_with_a_wrong_guess() {
+        // `g` returns whatever `f` returns, which is nothing, so `f` has nothing to store
+        let source = "fn f arity=0 locals=1\n    IPUSH 1\n    IPUSH 0\n    ICMPGT\n    JUMPNOTIF base\n\
+                      \x20   INVOKE g\n    LSTORE 0\nbase:\n    RETURN\nend\n\
+                      fn g arity=0 locals=0\n    INVOKE f\n    RETURN\nend\n\
+                      fn main arity=0 locals=0\n    INVOKE g\n    IPUSH 1\n    IADD\n    RETURN\nend\n";
+        assert_eq!(verify_source(source), Err("In function `f` at offset 0005: Stack underflow".to_string()));
+    }
+
+    #[test]
+    fn code_after_a_call_that_never_returns_is_unreachable() {
+        let source = "fn forever arity=0 locals=0\n    INVOKE forever\n    RETURN\nend\n\
+                      fn main arity=0 locals=0\n    INVOKE forever\n    IADD\n    RETURN\nend\n";
+        assert_eq!(verify_source(source), Ok(()));
+        assert_eq!(verify_source(include_str!("../../examples/fib.basm")), Ok(()));
+    }
+
+    #[test]
+    fn rejects_running_past_the_end() {
+        assert_eq!(
+            verify_main(0, "NOP"),
+            Err("In function `main` at offset 0000: Execution runs past the last instruction".to_string())
+        );
+    }
+}